@@ -1,6 +1,8 @@
-
-
 fn main() {
+    // With the `dlopen` feature, the libraries are loaded at runtime instead, see `src/ffi/library.rs`
+    if cfg!(feature = "dlopen") {
+        return;
+    }
     println!("cargo:rustc-link-lib=dylib=X11");
 
     #[cfg(feature = "shm")]
@@ -8,4 +10,7 @@ fn main() {
 
     #[cfg(feature = "xrandr")]
     println!("cargo:rustc-link-lib=dylib=Xrandr");
+
+    #[cfg(feature = "damage")]
+    println!("cargo:rustc-link-lib=dylib=Xdamage");
 }
@@ -0,0 +1,147 @@
+//!
+//! A common interface to every way of capturing frames.
+//!
+//! [`Capturer`] is implemented by
+//! - [`Display`], capturing the whole display through `XGetImage`
+//! - [`Region`], capturing an area of a display through `XGetImage`
+//! - [`CaptureSession`](crate::session::CaptureSession), using MIT-SHM where available
+//! - [`SharedSession`](crate::shm::SharedSession), with the `shm` feature
+//!
+//! Code that processes frames, e.g. the [`Recorder`](crate::record::Recorder), can be generic over it
+//! instead of handling each backend separately. Backends hand out their frames either as an owned [`Image`],
+//! or borrowed from a buffer that is reused by the next capture, [`Frame`] covers both.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, capture::{Capturer, CaptureError}};
+//! fn brightness(capturer: &mut impl Capturer) -> Result<u64, CaptureError> {
+//!     let frame = capturer.capture()?;
+//!     let pixels = frame.pixels();
+//!     Ok((0..pixels.height()).flat_map(|y| pixels.rgb_row(y)).map(|[r, g, b]| (r as u64 + g as u64 + b as u64) / 3).sum())
+//! }
+//!
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let whole = brightness(&mut &display);
+//!     let corner = brightness(&mut display.region((0, 0), (100, 100)));
+//! }
+//! ```
+//!
+
+use std::ops::Deref;
+use crate::{Display, Image};
+
+#[derive(PartialEq, Debug)]
+pub enum CaptureError {
+    /// The backend failed to capture, e.g. `XGetImage` failed
+    CaptureFailed,
+    /// The capturer has no more frames, e.g. at the end of a replayed recording
+    Exhausted
+}
+
+/// The area a [`Capturer`] captures, in coordinates of its display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub offset: (u32, u32),
+    pub size: (u32, u32)
+}
+
+impl Geometry {
+    pub fn new(offset: (u32, u32), size: (u32, u32)) -> Self {
+        Self { offset, size }
+    }
+}
+
+/// A captured frame, either owned or borrowed from a buffer of the capturer
+pub enum Frame<'a> {
+    Owned(Image),
+    Borrowed(&'a Image)
+}
+
+impl<'a> Deref for Frame<'a> {
+    type Target = Image;
+    fn deref(&self) -> &Image {
+        match self {
+            Frame::Owned(image) => image,
+            Frame::Borrowed(image) => image
+        }
+    }
+}
+
+/// A source of frames, see the [module documentation](self)
+pub trait Capturer {
+    /// Capture the next frame
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError>;
+    /// The area the frames are captured from
+    fn geometry(&self) -> Geometry;
+}
+
+impl<C: Capturer + ?Sized> Capturer for &mut C {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        (**self).capture()
+    }
+    fn geometry(&self) -> Geometry {
+        (**self).geometry()
+    }
+}
+
+impl Capturer for &Display {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        Display::capture(self).map(Frame::Owned).map_err(|_| CaptureError::CaptureFailed)
+    }
+    fn geometry(&self) -> Geometry {
+        Geometry::new((0, 0), (self.width, self.height))
+    }
+}
+
+impl Capturer for Display {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        Display::capture(self).map(Frame::Owned).map_err(|_| CaptureError::CaptureFailed)
+    }
+    fn geometry(&self) -> Geometry {
+        Capturer::geometry(&self)
+    }
+}
+
+/// Captures an area of a display through `XGetImage`, see [`Display::region`]
+#[derive(Clone, Copy)]
+pub struct Region<'a> {
+    display: &'a Display,
+    geometry: Geometry
+}
+
+impl<'a> Capturer for Region<'a> {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        self.display.capture_area(self.geometry.offset, self.geometry.size)
+            .map(Frame::Owned)
+            .map_err(|_| CaptureError::CaptureFailed)
+    }
+    fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+}
+
+impl Display {
+    /// A [`Capturer`] of the area of `size` (width, height) at `offset` (x, y)
+    pub fn region(&self, offset: (u32, u32), size: (u32, u32)) -> Region<'_> {
+        Region { display: self, geometry: Geometry::new(offset, size) }
+    }
+}
+
+impl<'a> Capturer for crate::session::CaptureSession<'a> {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        crate::session::CaptureSession::capture(self).map(Frame::Borrowed).map_err(|_| CaptureError::CaptureFailed)
+    }
+    fn geometry(&self) -> Geometry {
+        Geometry::new(self.offset(), self.area())
+    }
+}
+
+#[cfg(feature = "shm")]
+impl<'a> Capturer for crate::shm::SharedSession<'a> {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        crate::shm::SharedSession::capture(self).map(Frame::Borrowed).map_err(|_| CaptureError::CaptureFailed)
+    }
+    fn geometry(&self) -> Geometry {
+        Geometry::new(self.offset(), self.area())
+    }
+}
@@ -0,0 +1,322 @@
+//!
+//! Frame pacing for capturing at a fixed rate.
+//!
+//! A [`FrameClock`] divides time into slots of equal length, starting at its first tick.
+//! [`FrameClock::tick`] sleeps until the next slot starts, based on the monotonic clock, so the pace
+//! doesn't drift no matter how long the work on each frame takes. When a frame took longer than a
+//! slot, the slots that passed in the meantime are skipped and counted as dropped, instead of
+//! capturing a burst of frames to catch up.
+//!
+//! The latency statistics cover the most recent [`LATENCY_WINDOW`] frames, so a long running clock
+//! uses a bounded amount of memory. The clock reads and sleeps through a [`TimeSource`], which is the
+//! monotonic clock of the system unless given otherwise.
+//!
+//! [`CaptureLoop`] paces the captures of any [`Capturer`], e.g. a
+//! [`SharedSession`](crate::shm::SharedSession).
+//!
+//! # Usage
+//! ```rust,no_run
+//! # use rxscreen::{Display, clock::FrameClock};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let mut clock = FrameClock::new(60);
+//!     for _ in 0..600 {
+//!         let tick = clock.tick();
+//!         let capture = display.capture();
+//!         clock.done(&tick);
+//!     }
+//!     let stats = clock.stats();
+//!     println!("{:.1} fps, {} dropped, 99th percentile latency {:?}", stats.fps(), stats.dropped, stats.latency(99.0));
+//! }
+//! ```
+//!
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use crate::{Image, capture::{CaptureError, Capturer, Frame}};
+
+/// The amount of most recent frames whose latencies are kept for [`FrameStats::latency`]
+pub const LATENCY_WINDOW: usize = 1024;
+
+/// The clock a [`FrameClock`] reads and sleeps on
+pub trait TimeSource {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The monotonic clock of the system, through [`Instant::now`] and [`std::thread::sleep`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Monotonic;
+
+impl TimeSource for Monotonic {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// A slot of a [`FrameClock`], handed out by [`FrameClock::tick`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tick {
+    /// The index of the slot, counted from the first tick
+    pub index: u64,
+    /// When the tick fired, relative to the first tick
+    pub timestamp: Duration,
+    /// The amount of slots skipped right before this one, as the previous frame took too long
+    pub dropped: u64,
+    fired: Instant
+}
+
+/// Statistics of the frames paced by a [`FrameClock`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStats {
+    /// The amount of frames completed through [`FrameClock::done`]
+    pub frames: u64,
+    /// The amount of skipped slots
+    pub dropped: u64,
+    /// The time since the first tick
+    pub elapsed: Duration,
+    /// The latencies of the last [`LATENCY_WINDOW`] completed frames, from their tick to [`FrameClock::done`], sorted
+    latencies: Vec<Duration>
+}
+
+impl FrameStats {
+    /// The achieved rate of completed frames per second
+    pub fn fps(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.frames as f64 / self.elapsed.as_secs_f64()
+    }
+    /// The latency below which `percentile` (0 to 100) percent of the recent frames were completed,
+    /// e.g. `latency(50.0)` for the median. `None` if no frame has been completed yet.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * last as f64).round() as usize;
+        Some(self.latencies[rank])
+    }
+}
+
+/// Paces frames at a fixed rate, see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct FrameClock<T: TimeSource = Monotonic> {
+    interval: Duration,
+    time: T,
+    start: Option<Instant>,
+    /// The index of the next slot
+    slot: u64,
+    dropped: u64,
+    frames: u64,
+    /// The latencies of the last [`LATENCY_WINDOW`] frames, oldest first
+    latencies: VecDeque<Duration>
+}
+
+impl FrameClock {
+    /// A clock ticking `fps` times per second
+    pub fn new(fps: u32) -> Self {
+        Self::with_interval(Duration::from_secs(1) / fps.max(1))
+    }
+    /// A clock ticking every `interval`
+    pub fn with_interval(interval: Duration) -> Self {
+        Self::with_time_source(interval, Monotonic)
+    }
+}
+
+/// The offset of the start of `slot` from the first tick, `None` if it can't be represented
+fn slot_offset(interval: Duration, slot: u64) -> Option<Duration> {
+    let nanos = interval.as_nanos().checked_mul(slot as u128)?;
+    Some(Duration::new(u64::try_from(nanos / 1_000_000_000).ok()?, (nanos % 1_000_000_000) as u32))
+}
+
+impl<T: TimeSource> FrameClock<T> {
+    /// A clock ticking every `interval`, reading and sleeping on `time`
+    pub fn with_time_source(interval: Duration, time: T) -> Self {
+        Self {
+            interval: interval.max(Duration::from_nanos(1)),
+            time,
+            start: None,
+            slot: 0,
+            dropped: 0,
+            frames: 0,
+            latencies: VecDeque::with_capacity(LATENCY_WINDOW)
+        }
+    }
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Sleep until the next slot starts, and return it.
+    /// The first tick returns immediately and starts the clock.
+    pub fn tick(&mut self) -> Tick {
+        let time = &self.time;
+        let start = *self.start.get_or_insert_with(|| time.now());
+        let due = slot_offset(self.interval, self.slot).and_then(|offset| start.checked_add(offset));
+        if let Some(wait) = due.and_then(|due| due.checked_duration_since(self.time.now())) {
+            self.time.sleep(wait);
+        }
+
+        let fired = self.time.now();
+        let timestamp = fired.saturating_duration_since(start);
+        // Skip the slots that passed while the previous frame was in progress
+        let current = u64::try_from(timestamp.as_nanos() / self.interval.as_nanos()).unwrap_or(u64::MAX);
+        let dropped = current.saturating_sub(self.slot);
+        self.dropped += dropped;
+        self.slot = self.slot.max(current) + 1;
+        Tick { index: self.slot - 1, timestamp, dropped, fired }
+    }
+
+    /// Mark the frame of `tick` as completed, recording its latency.
+    pub fn done(&mut self, tick: &Tick) {
+        if self.latencies.len() == LATENCY_WINDOW {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(self.time.now().saturating_duration_since(tick.fired));
+        self.frames += 1;
+    }
+
+    /// The time since the first tick
+    pub fn elapsed(&self) -> Duration {
+        self.start.map_or(Duration::ZERO, |start| self.time.now().saturating_duration_since(start))
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        let mut latencies = Vec::from(self.latencies.clone());
+        latencies.sort_unstable();
+        FrameStats {
+            frames: self.frames,
+            dropped: self.dropped,
+            elapsed: self.elapsed(),
+            latencies
+        }
+    }
+}
+
+/// Captures of a [`Capturer`], paced by a [`FrameClock`].
+///
+/// ```rust,no_run
+/// # use rxscreen::{Display, clock::CaptureLoop, session::CaptureSession};
+/// if let Ok(display) = Display::new(":0.0") {
+///     let mut capture = CaptureLoop::new(CaptureSession::full(&display), 30);
+///     let stats = capture.run(|frame, tick| {
+///         // Process `frame`, return `false` to stop
+///         tick.index < 300
+///     });
+///     println!("{:.1} fps, {} dropped", stats.fps(), stats.dropped);
+/// }
+/// ```
+pub struct CaptureLoop<C: Capturer> {
+    capturer: C,
+    clock: FrameClock
+}
+
+impl<C: Capturer> CaptureLoop<C> {
+    /// Capture through `capturer` at `fps` frames per second
+    pub fn new(capturer: C, fps: u32) -> Self {
+        Self { capturer, clock: FrameClock::new(fps) }
+    }
+
+    /// Wait for the next slot and capture a frame.
+    /// The latency of the frame covers the capture only, see [`CaptureLoop::run`] to include its processing.
+    pub fn capture(&mut self) -> Result<(Frame<'_>, Tick), CaptureError> {
+        let tick = self.clock.tick();
+        let frame = self.capturer.capture()?;
+        self.clock.done(&tick);
+        Ok((frame, tick))
+    }
+
+    /// Capture frames and pass them to `frame`, until it returns `false` or a capture fails.
+    /// The latency of each frame covers its capture and processing by `frame`.
+    pub fn run(&mut self, mut frame: impl FnMut(&Image, &Tick) -> bool) -> FrameStats {
+        loop {
+            let tick = self.clock.tick();
+            let Ok(image) = self.capturer.capture() else { break };
+            let proceed = frame(&image, &tick);
+            drop(image);
+            self.clock.done(&tick);
+            if !proceed {
+                break;
+            }
+        }
+        self.clock.stats()
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.clock.stats()
+    }
+}
+
+/// A clock that only advances when slept on, or through [`FakeTime::advance`]
+#[cfg(test)]
+#[derive(Debug, Clone)]
+struct FakeTime {
+    origin: Instant,
+    offset: std::rc::Rc<std::cell::Cell<Duration>>
+}
+
+#[cfg(test)]
+impl FakeTime {
+    fn new() -> Self {
+        Self { origin: Instant::now(), offset: Default::default() }
+    }
+    fn advance(&self, duration: Duration) {
+        self.offset.set(self.offset.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl TimeSource for FakeTime {
+    fn now(&self) -> Instant {
+        self.origin + self.offset.get()
+    }
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[test]
+fn skips_slots_under_load() {
+    let time = FakeTime::new();
+    let mut clock = FrameClock::with_time_source(Duration::from_millis(50), time.clone());
+    let first = clock.tick();
+    assert_eq!((first.index, first.dropped), (0, 0));
+    time.advance(Duration::from_millis(10));
+    clock.done(&first);
+
+    // The frame of slot 0 takes until slot 3, slots 1 and 2 are dropped
+    time.advance(Duration::from_millis(150));
+    let late = clock.tick();
+    assert_eq!((late.index, late.dropped, late.timestamp), (3, 2, Duration::from_millis(160)));
+    time.advance(Duration::from_millis(30));
+    clock.done(&late);
+
+    let next = clock.tick();
+    assert_eq!((next.index, next.dropped, next.timestamp), (4, 0, Duration::from_millis(200)));
+
+    let stats = clock.stats();
+    assert_eq!((stats.frames, stats.dropped, stats.elapsed), (2, 2, Duration::from_millis(200)));
+    assert_eq!((stats.latency(0.0), stats.latency(100.0)), (Some(Duration::from_millis(10)), Some(Duration::from_millis(30))));
+}
+
+#[test]
+fn keeps_the_latencies_of_recent_frames() {
+    let time = FakeTime::new();
+    let mut clock = FrameClock::with_time_source(Duration::from_millis(1), time.clone());
+    for latency in 0..LATENCY_WINDOW as u64 + 100 {
+        let tick = clock.tick();
+        time.advance(Duration::from_micros(latency));
+        clock.done(&tick);
+    }
+    let stats = clock.stats();
+    assert_eq!(stats.frames, LATENCY_WINDOW as u64 + 100);
+    assert_eq!(stats.latencies.len(), LATENCY_WINDOW);
+    assert_eq!(stats.latency(0.0), Some(Duration::from_micros(100)));
+}
+
+#[test]
+fn computes_slot_offsets_without_truncation() {
+    let slot = u32::MAX as u64 + 2;
+    assert_eq!(slot_offset(Duration::from_millis(1), slot), Some(Duration::from_millis(slot)));
+    assert_eq!(slot_offset(Duration::from_secs(u64::MAX), 2), None);
+}
@@ -0,0 +1,93 @@
+//!
+//! BMP encoder, writing uncompressed 24-bit bitmaps, and decoder for uncompressed 24 and 32-bit bitmaps.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use super::{Decoded, Pixels, invalid};
+
+const FILE_HEADER_SIZE: u32 = 14;
+const INFO_HEADER_SIZE: u32 = 40;
+
+/// Encode `pixels` as an uncompressed 24-bit BMP
+pub fn encode(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    let (width, height) = (pixels.width(), pixels.height());
+    // Rows are padded to a multiple of 4 bytes
+    let row_size = (width * 3 + 3) & !3;
+    let image_size = u32::try_from(row_size * height)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Image too large for BMP"))?;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+
+    let mut header = Vec::with_capacity(offset as usize);
+    header.extend_from_slice(b"BM");
+    header.extend_from_slice(&(offset + image_size).to_le_bytes());
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&offset.to_le_bytes());
+
+    header.extend_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+    header.extend_from_slice(&(width as i32).to_le_bytes());
+    header.extend_from_slice(&(height as i32).to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // planes
+    header.extend_from_slice(&24u16.to_le_bytes()); // bits per pixel
+    header.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+    header.extend_from_slice(&image_size.to_le_bytes());
+    header.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI
+    header.extend_from_slice(&2835i32.to_le_bytes());
+    header.extend_from_slice(&[0; 8]); // palette size, important colors
+    writer.write_all(&header)?;
+
+    // Bitmaps are stored bottom-up, with the pixels in BGR order
+    let mut row = vec![0u8; row_size];
+    for y in (0..height).rev() {
+        for (target, bgrx) in row.chunks_exact_mut(3).zip(pixels.row(y).chunks_exact(4)) {
+            target.copy_from_slice(&bgrx[..3]);
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+/// Decode an uncompressed 24 or 32-bit BMP into RGB pixels
+pub fn decode(data: &[u8]) -> io::Result<Decoded> {
+    let field = |offset: usize| data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid("Truncated BMP header"));
+    if !data.starts_with(b"BM") {
+        return Err(invalid("Missing BMP signature"));
+    }
+    let offset = field(10)? as usize;
+    let (width, height) = (field(18)? as i32, field(22)? as i32);
+    let bits = data.get(28).copied().ok_or_else(|| invalid("Truncated BMP header"))? as usize;
+    // BI_RGB, and BI_BITFIELDS which 32-bit bitmaps commonly use with the default masks
+    if !matches!((field(30)?, bits), (0, 24 | 32) | (3, 32)) || width < 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Only uncompressed 24 and 32-bit BMPs are supported"));
+    }
+
+    let (width, rows) = (width as usize, height.unsigned_abs() as usize);
+    let mut image = Decoded::new(width, rows, data.len())?;
+    let bytes_per_pixel = bits / 8;
+    let row_size = (width * bytes_per_pixel + 3) & !3;
+    let pixels = data.get(offset..).filter(|pixels| pixels.len() >= row_size * rows)
+        .ok_or_else(|| invalid("Truncated BMP image data"))?;
+    for y in 0..rows {
+        // Positive heights are stored bottom-up
+        let row = if height > 0 { rows - 1 - y } else { y };
+        for bgr in pixels[row * row_size..row * row_size + width * bytes_per_pixel].chunks_exact(bytes_per_pixel) {
+            image.data.extend_from_slice(&[bgr[2], bgr[1], bgr[0]]);
+        }
+    }
+    Ok(image)
+}
+
+#[test]
+fn encodes_padded_bottom_up_rows() {
+    // 1x2 image, top pixel red, bottom pixel blue
+    let data = [0, 0, 0xff, 0, 0xff, 0, 0, 0];
+    let pixels = Pixels::new(&data, 1, 2, 4).unwrap();
+    let mut bmp = vec![];
+    encode(&mut bmp, &pixels).unwrap();
+    assert_eq!(bmp.len(), 54 + 2 * 4);
+    assert_eq!(&bmp[..2], b"BM");
+    assert_eq!(&bmp[54..], &[0xff, 0, 0, 0, 0, 0, 0xff, 0]);
+    assert_eq!(decode(&bmp).unwrap().data, [0xff, 0, 0, 0, 0, 0xff]);
+}
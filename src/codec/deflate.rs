@@ -0,0 +1,153 @@
+//!
+//! A small zlib compressor (RFC 1950/1951), using LZ77 with the fixed huffman codes.
+//!
+//! It doesn't get close to the ratios of zlib, but captures of screen content consist
+//! of long repetitions, which it already compresses well.
+//!
+
+use super::png::Adler32;
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried for each match
+const MAX_CHAIN: usize = 32;
+
+/// Base lengths of the length codes 257..285
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// Base distances of the distance codes 0..29
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u32
+}
+
+impl BitWriter {
+    /// Write the `count` lowest bits of `value`, least significant bit first
+    fn write(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.bits;
+        self.bits += count;
+        while self.bits >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+    /// Write a huffman code of `length` bits, which are stored most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+    fn flush(&mut self) {
+        if self.bits > 0 {
+            self.out.push(self.buffer as u8);
+            self.buffer = 0;
+            self.bits = 0;
+        }
+    }
+}
+
+/// Write `symbol` of the literal/length alphabet with its fixed huffman code
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8)
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap_or(0);
+    write_symbol(writer, 257 + code as u32);
+    writer.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+
+    let code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap_or(0);
+    writer.write_code(code as u32, 5);
+    writer.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (value.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compress `data` into a zlib stream
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::with_capacity(data.len() / 4 + 16), buffer: 0, bits: 0 };
+    // CMF (deflate, 32K window) and FLG (no dictionary, default level)
+    writer.out.extend_from_slice(&[0x78, 0x9c]);
+    // A single, final block with the fixed codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    // The most recent position of each hash, and the previous position with the same hash of each position
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+    let insert = |head: &mut [usize], previous: &mut [usize], position: usize| {
+        if position + MIN_MATCH <= data.len() {
+            let hash = hash(&data[position..]);
+            previous[position % WINDOW] = head[hash];
+            head[hash] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(&data[position..])];
+            let limit = (data.len() - position).min(MAX_MATCH);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || position - candidate > WINDOW - 1 {
+                    break;
+                }
+                let length = data[candidate..].iter().zip(&data[position..position + limit]).take_while(|(a, b)| a == b).count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                    if length == limit {
+                        break;
+                    }
+                }
+                let next = previous[candidate % WINDOW];
+                // Entries of the chain may have been overwritten by newer positions
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            for offset in 0..best_length {
+                insert(&mut head, &mut previous, position + offset);
+            }
+            position += best_length;
+        } else {
+            write_symbol(&mut writer, data[position] as u32);
+            insert(&mut head, &mut previous, position);
+            position += 1;
+        }
+    }
+    write_symbol(&mut writer, 256);
+    writer.flush();
+
+    let mut adler = Adler32::new();
+    adler.update(data);
+    writer.out.extend_from_slice(&adler.finish().to_be_bytes());
+    writer.out
+}
+
+#[test]
+fn compresses_repetitions() {
+    let data = b"rxscreen rxscreen rxscreen, abcabcabcabcabc and some literals".repeat(100);
+    let compressed = compress(&data);
+    assert!(compressed.len() < data.len() / 10);
+    assert_eq!(super::inflate::decompress(&compressed, data.len()).unwrap(), data);
+}
@@ -0,0 +1,232 @@
+//!
+//! Decompression of zlib streams (RFC 1950/1951), as used by the PNG decoder.
+//!
+
+use std::io;
+use super::{invalid, png::Adler32};
+
+/// Base lengths and extra bits of the length codes 257..285
+const LENGTHS: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0), (11, 1), (13, 1), (15, 1), (17, 1),
+    (19, 2), (23, 2), (27, 2), (31, 2), (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4),
+    (115, 4), (131, 5), (163, 5), (195, 5), (227, 5), (258, 0)
+];
+
+/// Base distances and extra bits of the distance codes 0..29
+const DISTANCES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2), (17, 3), (25, 3), (33, 4), (49, 4),
+    (65, 5), (97, 5), (129, 6), (193, 6), (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9),
+    (2049, 10), (3073, 10), (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13)
+];
+
+/// The order in which the code lengths of the code length alphabet are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32
+}
+
+impl<'a> Bits<'a> {
+    fn read(&mut self, count: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position).ok_or_else(|| invalid("Unexpected end of deflate stream"))?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+/// A canonical huffman code, decoded one bit at a time
+struct Huffman {
+    /// Amount of codes per length
+    counts: [u16; 16],
+    /// Symbols ordered by their code
+    symbols: Vec<u16>
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        lengths.iter().for_each(|length| counts[*length as usize] += 1);
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate().filter(|(_, length)| **length != 0) {
+            symbols[offsets[*length as usize] as usize] = symbol as u16;
+            offsets[*length as usize] += 1;
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("Invalid huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> io::Result<(Huffman, Huffman)> {
+    let literals = bits.read(5)? as usize + 257;
+    let distances = bits.read(5)? as usize + 1;
+    let code_lengths = bits.read(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for position in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[*position] = bits.read(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid("Repeated code length without a previous one"))?, 3 + bits.read(2)?),
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?)
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("Too many code lengths"));
+    }
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+/// Decompress the zlib stream `data`, which may decompress to at most `limit` bytes
+pub(crate) fn decompress(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    if data.len() < 2 || data[0] & 0x0f != 8 || (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 != 0 || data[1] & 0x20 != 0 {
+        return Err(invalid("Invalid zlib header"));
+    }
+    let mut bits = Bits { data: &data[2..], position: 0, bit: 0 };
+    let mut out = Vec::new();
+    let too_large = || invalid("Deflate stream exceeds the expected size");
+
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let header = bits.data.get(bits.position..bits.position + 4).ok_or_else(|| invalid("Unexpected end of deflate stream"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if length as u16 != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(invalid("Corrupt stored block length"));
+                }
+                let start = bits.position + 4;
+                if out.len() + length > limit {
+                    return Err(too_large());
+                }
+                out.extend_from_slice(bits.data.get(start..start + length).ok_or_else(|| invalid("Unexpected end of deflate stream"))?);
+                bits.position = start + length;
+            },
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 { fixed_codes() } else { dynamic_codes(&mut bits)? };
+                loop {
+                    let symbol = literals.decode(&mut bits)? as usize;
+                    if symbol < 256 {
+                        if out.len() == limit {
+                            return Err(too_large());
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let (base, extra) = *LENGTHS.get(symbol - 257).ok_or_else(|| invalid("Invalid length code"))?;
+                    let length = base as usize + bits.read(extra as u32)? as usize;
+                    let (base, extra) = *DISTANCES.get(distances.decode(&mut bits)? as usize).ok_or_else(|| invalid("Invalid distance code"))?;
+                    let distance = base as usize + bits.read(extra as u32)? as usize;
+                    if distance > out.len() {
+                        return Err(invalid("Distance exceeds the decompressed data"));
+                    }
+                    if out.len() + length > limit {
+                        return Err(too_large());
+                    }
+                    let start = out.len() - distance;
+                    for i in 0..length {
+                        out.push(out[start + i]);
+                    }
+                }
+            },
+            _ => return Err(invalid("Invalid deflate block type"))
+        }
+        if last {
+            break;
+        }
+    }
+
+    bits.align();
+    let checksum = bits.data.get(bits.position..bits.position + 4).ok_or_else(|| invalid("Missing zlib checksum"))?;
+    let mut adler = Adler32::new();
+    adler.update(&out);
+    if adler.finish().to_be_bytes() != checksum {
+        return Err(invalid("Zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[test]
+fn decompresses_fixed_and_stored_blocks() {
+    // zlib.compress(b"abcabcabcabc")
+    let fixed = [0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00, 0x1d, 0xe0, 0x04, 0x99];
+    assert_eq!(decompress(&fixed, 12).unwrap(), b"abcabcabcabc");
+    assert_eq!(decompress(&super::png::store(b"stored"), 6).unwrap(), b"stored");
+}
+
+#[test]
+fn decompresses_dynamic_blocks() {
+    // zlib.compress(b"the quick brown fox jumps over the lazy dog, " * 3 + b"pack my box with five dozen liquor jugs", 9)
+    let dynamic = [
+        0x78, 0xda, 0xb5, 0xcb, 0xc9, 0x11, 0x80, 0x20, 0x10, 0x44, 0xd1, 0x54, 0x3a, 0x00, 0x93, 0x02, 0x65, 0x53,
+        0x60, 0xd8, 0x11, 0xa3, 0x77, 0xca, 0x1c, 0x3c, 0x76, 0xfd, 0xd7, 0xcd, 0x2a, 0xe4, 0xee, 0xf6, 0x0b, 0xb2,
+        0xd0, 0x8c, 0xd0, 0x74, 0xe3, 0xec, 0x21, 0x55, 0xd0, 0x50, 0x05, 0x8d, 0xb3, 0x17, 0xcf, 0xc2, 0x41, 0x66,
+        0xfb, 0xd6, 0x3f, 0x38, 0x09, 0x76, 0x61, 0x41, 0x32, 0x9a, 0xae, 0x59, 0x68, 0x37, 0x14, 0xa7, 0x47, 0x45,
+        0x78, 0x97, 0x3b, 0x15, 0xfe, 0x9a, 0xfa, 0x02, 0xa7, 0x2a, 0x3f, 0x59
+    ];
+    assert_eq!((dynamic[2] >> 1) & 3, 2, "the fixture starts with a dynamic huffman block");
+    let mut expected = b"the quick brown fox jumps over the lazy dog, ".repeat(3);
+    expected.extend_from_slice(b"pack my box with five dozen liquor jugs");
+    assert_eq!(decompress(&dynamic, expected.len()).unwrap(), expected);
+}
+
+#[test]
+fn stops_at_the_expected_size() {
+    let fixed = decompress(&[0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00, 0x1d, 0xe0, 0x04, 0x99], 11);
+    assert_eq!(fixed.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(decompress(&super::png::store(b"stored"), 5).is_err());
+}
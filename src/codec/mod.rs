@@ -0,0 +1,229 @@
+//!
+//! Built-in, dependency-free image encoders and decoders.
+//!
+//! Unlike the `save` feature, which uses the `image` crate, these encoders are always available
+//! and add next to nothing to compile times and binary size. They support a small set of simple formats:
+//! - PPM (binary `P6`) and PAM (`P7`)
+//! - BMP (24-bit, uncompressed)
+//! - [QOI](https://qoiformat.org)
+//! - PNG, with the image data stored in uncompressed deflate blocks
+//!
+//! The decoders read the same formats, with PNG being decoded in full (8 and 16-bit, any color type,
+//! without interlacing), 32-bit BMPs and PAMs of any tuple type with a maximum value of 255.
+//! Transparency is dropped, as X11 visuals carry no alpha channel.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, codec::Encoding};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let capture = display.capture().unwrap();
+//!     let file = std::fs::File::create("./capture.qoi").unwrap();
+//!     capture.write_as(std::io::BufWriter::new(file), Encoding::Qoi).unwrap();
+//! }
+//! ```
+//!
+
+use std::io::{self, Write};
+use std::path::Path;
+use crate::{Display, Image};
+
+pub mod bmp;
+pub(crate) mod deflate;
+mod inflate;
+pub mod netpbm;
+pub mod png;
+pub mod qoi;
+
+/// A borrowed view of 32-bit BGRX pixel data, as captured from the X server.
+#[derive(Debug, Clone, Copy)]
+pub struct Pixels<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    stride: usize
+}
+
+impl<'a> Pixels<'a> {
+    /// View `data` as `width` x `height` BGRX pixels, with `stride` bytes per row.
+    ///
+    /// Returns `None` if `data` is too short for the given dimensions.
+    pub fn new(data: &'a [u8], width: usize, height: usize, stride: usize) -> Option<Self> {
+        if stride < width * 4 || (height > 0 && data.len() < stride * (height - 1) + width * 4) {
+            return None;
+        }
+        Some(Self { data, width, height, stride })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// The BGRX pixels of row `y`
+    pub fn row(&self, y: usize) -> &'a [u8] {
+        &self.data[y * self.stride..y * self.stride + self.width * 4]
+    }
+    /// The pixels of row `y` as RGB triplets
+    pub fn rgb_row(&self, y: usize) -> impl Iterator<Item = [u8; 3]> + 'a {
+        self.row(y).chunks_exact(4).map(|bgrx| [bgrx[2], bgrx[1], bgrx[0]])
+    }
+}
+
+/// An image decoded by one of the built-in decoders, as tightly packed RGB pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>
+}
+
+impl Decoded {
+    /// Allocate the buffer for a `width` x `height` image, making sure that `available` bytes of
+    /// input can plausibly describe it, so corrupt headers can't request huge allocations.
+    pub(crate) fn new(width: usize, height: usize, available: usize) -> io::Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(invalid("Image has no pixels"));
+        }
+        let pixels = width.checked_mul(height).filter(|pixels| *pixels <= available.saturating_mul(1024))
+            .ok_or_else(|| invalid("Image dimensions exceed the encoded data"))?;
+        Ok(Self { width, height, data: Vec::with_capacity(pixels * 3) })
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The formats supported by the built-in encoders and decoders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Ppm,
+    Pam,
+    Bmp,
+    Qoi,
+    /// PNG with stored (uncompressed) deflate blocks
+    Png
+}
+
+impl Encoding {
+    /// Guess the encoding from the extension of `path`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Encoding> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" | "pnm" => Some(Encoding::Ppm),
+            "pam" => Some(Encoding::Pam),
+            "bmp" => Some(Encoding::Bmp),
+            "qoi" => Some(Encoding::Qoi),
+            "png" => Some(Encoding::Png),
+            _ => None
+        }
+    }
+
+    /// Detect the encoding of `data` from its signature
+    pub fn detect(data: &[u8]) -> Option<Encoding> {
+        match data {
+            [b'P', b'6', ..] => Some(Encoding::Ppm),
+            [b'P', b'7', ..] => Some(Encoding::Pam),
+            [b'B', b'M', ..] => Some(Encoding::Bmp),
+            data if data.starts_with(qoi::MAGIC) => Some(Encoding::Qoi),
+            data if data.starts_with(&png::SIGNATURE) => Some(Encoding::Png),
+            _ => None
+        }
+    }
+
+    /// Decode `data` into RGB pixels
+    pub fn decode(&self, data: &[u8]) -> io::Result<Decoded> {
+        match self {
+            Encoding::Ppm | Encoding::Pam => netpbm::decode(data),
+            Encoding::Bmp => bmp::decode(data),
+            Encoding::Qoi => qoi::decode(data),
+            Encoding::Png => png::decode(data)
+        }
+    }
+
+    /// Encode `pixels` and write them to `writer`
+    pub fn encode(&self, writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+        match self {
+            Encoding::Ppm => netpbm::encode_ppm(writer, pixels),
+            Encoding::Pam => netpbm::encode_pam(writer, pixels),
+            Encoding::Bmp => bmp::encode(writer, pixels),
+            Encoding::Qoi => qoi::encode(writer, pixels),
+            Encoding::Png => png::encode(writer, pixels)
+        }
+    }
+}
+
+impl Image {
+    /// A view of the pixel data, for use with the built-in encoders
+    pub fn pixels(&self) -> Pixels<'_> {
+        unsafe {
+            let (width, height) = (self.width().max(0) as usize, self.height().max(0) as usize);
+            let stride = (*self.raw).bytes_per_line as usize;
+            Pixels {
+                data: std::slice::from_raw_parts(self.as_ptr(), stride * height),
+                width,
+                height,
+                stride
+            }
+        }
+    }
+
+    /// Encode the image with the built-in encoder for `encoding`, and write it to `writer`
+    pub fn write_as(&self, mut writer: impl Write, encoding: Encoding) -> io::Result<()> {
+        encoding.encode(&mut writer, &self.pixels())?;
+        writer.flush()
+    }
+
+    /// Saves the image to file
+    ///
+    /// Without the `save` feature the built-in encoders are used, the format is inferred from the
+    /// extension of `file`, see [`Encoding::from_path`].
+    ///
+    /// ```rust
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let screenshot = display.capture();
+    ///     screenshot.unwrap().save_as("./screenshot.bmp");
+    /// }
+    /// ```
+    #[cfg(not(feature = "save"))]
+    pub fn save_as(&self, file: impl Into<std::path::PathBuf>) -> io::Result<()> {
+        let file = file.into();
+        let encoding = Encoding::from_path(&file)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file extension"))?;
+        self.write_as(io::BufWriter::new(std::fs::File::create(file)?), encoding)
+    }
+
+    /// Decode an image file into an image in the format of `display`
+    ///
+    /// Without the `save` feature the built-in decoders are used, the format is detected from the
+    /// contents of `data`, see [`Encoding::detect`].
+    #[cfg(not(feature = "save"))]
+    pub fn decode(display: &Display, data: &[u8]) -> io::Result<Image> {
+        let encoding = Encoding::detect(data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported image format"))?;
+        Image::decode_as(display, data, encoding)
+    }
+
+    /// Decode `data` with the built-in decoder for `encoding`
+    pub(crate) fn decode_as(display: &Display, data: &[u8], encoding: Encoding) -> io::Result<Image> {
+        let decoded = encoding.decode(data)?;
+        Ok(Image::from_packed(display, decoded.width as u32, decoded.height as u32, 3, &decoded.data))
+    }
+
+    /// Load the image file at `path`, e.g. to draw it onto a window or compare it against a capture.
+    ///
+    /// ```rust
+    /// # use rxscreen::{Display, Image};
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let reference = Image::open(&display, "./reference.png");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read, or its format isn't supported, see [`Image::decode`].
+    pub fn open(display: &Display, path: impl AsRef<Path>) -> io::Result<Image> {
+        Image::decode(display, &std::fs::read(path)?)
+    }
+}
@@ -0,0 +1,98 @@
+//!
+//! Netpbm encoders and decoders: binary PPM (`P6`) and PAM (`P7`).
+//!
+
+use std::io::{self, Write};
+use super::{Decoded, Pixels, invalid};
+
+fn write_rgb(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    let mut row = Vec::with_capacity(pixels.width() * 3);
+    for y in 0..pixels.height() {
+        row.clear();
+        pixels.rgb_row(y).for_each(|rgb| row.extend_from_slice(&rgb));
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+/// Encode `pixels` as binary PPM
+pub fn encode_ppm(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", pixels.width(), pixels.height())?;
+    write_rgb(writer, pixels)
+}
+
+/// Encode `pixels` as PAM with the `RGB` tuple type
+pub fn encode_pam(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    write!(writer, "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 3\nMAXVAL 255\nTUPLTYPE RGB\nENDHDR\n", pixels.width(), pixels.height())?;
+    write_rgb(writer, pixels)
+}
+
+/// Split the header of a netpbm file into its tokens, skipping comments.
+/// Returns the tokens and the offset of the image data.
+fn tokens(data: &[u8], count: usize, end: Option<&str>) -> io::Result<(Vec<String>, usize)> {
+    let (mut tokens, mut position) = (Vec::new(), 2);
+    loop {
+        let done = match end {
+            Some(end) => tokens.last().map(String::as_str) == Some(end),
+            None => tokens.len() == count
+        };
+        if done {
+            // A single whitespace character separates the header from the data
+            return Ok((tokens, position + 1));
+        }
+        match data.get(position) {
+            Some(b'#') => position += data[position..].iter().position(|byte| *byte == b'\n').unwrap_or(data.len() - position),
+            Some(byte) if byte.is_ascii_whitespace() => position += 1,
+            Some(_) => {
+                let length = data[position..].iter().position(u8::is_ascii_whitespace).unwrap_or(data.len() - position);
+                tokens.push(String::from_utf8_lossy(&data[position..position + length]).into_owned());
+                position += length;
+            },
+            None => return Err(invalid("Truncated netpbm header"))
+        }
+    }
+}
+
+/// Decode a binary PPM, or a PAM with one to four channels, into RGB pixels
+pub fn decode(data: &[u8]) -> io::Result<Decoded> {
+    let number = |token: &str| token.parse::<usize>().map_err(|_| invalid("Invalid number in netpbm header"));
+    let (width, height, depth, maxval, offset) = match data.get(..2) {
+        Some(b"P6") => {
+            let (tokens, offset) = tokens(data, 3, None)?;
+            (number(&tokens[0])?, number(&tokens[1])?, 3, number(&tokens[2])?, offset)
+        },
+        Some(b"P7") => {
+            let (tokens, offset) = tokens(data, 0, Some("ENDHDR"))?;
+            let value = |key: &str| tokens.iter().position(|token| token == key)
+                .and_then(|index| tokens.get(index + 1))
+                .ok_or_else(|| invalid("Missing field in PAM header"))
+                .and_then(|value| number(value));
+            (value("WIDTH")?, value("HEIGHT")?, value("DEPTH")?, value("MAXVAL")?, offset)
+        },
+        _ => return Err(invalid("Missing netpbm signature"))
+    };
+    if maxval != 255 || !(1..=4).contains(&depth) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Only 8-bit netpbm images with up to four channels are supported"));
+    }
+
+    let mut image = Decoded::new(width, height, data.len())?;
+    let samples = data.get(offset..).filter(|samples| samples.len() >= width * height * depth)
+        .ok_or_else(|| invalid("Truncated netpbm image data"))?;
+    for pixel in samples[..width * height * depth].chunks_exact(depth) {
+        // Grayscale (with alpha) or RGB (with alpha)
+        let rgb = if depth < 3 { [pixel[0]; 3] } else { [pixel[0], pixel[1], pixel[2]] };
+        image.data.extend_from_slice(&rgb);
+    }
+    Ok(image)
+}
+
+#[test]
+fn encodes_ppm() {
+    let data = [0x30, 0x20, 0x10, 0, 0xff, 0xfe, 0xfd, 0];
+    let pixels = Pixels::new(&data, 2, 1, 8).unwrap();
+    let mut ppm = vec![];
+    encode_ppm(&mut ppm, &pixels).unwrap();
+    assert_eq!(ppm, b"P6\n2 1\n255\n\x10\x20\x30\xfd\xfe\xff");
+    assert_eq!(decode(&ppm).unwrap().data, b"\x10\x20\x30\xfd\xfe\xff");
+    assert_eq!(decode(b"P6 # comment\n1 1 255\n\x01\x02\x03").unwrap().data, [1, 2, 3]);
+}
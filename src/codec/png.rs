@@ -0,0 +1,265 @@
+//!
+//! PNG encoder without compression, and decoder.
+//!
+//! The image data is stored in uncompressed deflate blocks, which every PNG decoder understands.
+//! The files are therefore about as large as the raw RGB data, but encoding is very cheap.
+//!
+//! The decoder reads non-interlaced PNGs of any color type, with 8 or 16 bits per channel
+//! (palettes with 1 to 8 bits).
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use super::{Decoded, Pixels, invalid};
+
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The maximum length of a stored deflate block
+const MAX_BLOCK: usize = 65535;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// CRC-32 as used by PNG chunks (and zip/gzip)
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |c, byte| CRC_TABLE[((c ^ *byte as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+/// Running Adler-32 checksum, as used by zlib streams
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Adler32 {
+    a: u32,
+    b: u32
+}
+
+impl Adler32 {
+    pub(crate) fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        // 5552 is the largest amount of bytes that can be summed before `b` may overflow
+        for chunk in data.chunks(5552) {
+            for byte in chunk {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= 65521;
+            self.b %= 65521;
+        }
+    }
+    pub(crate) fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+/// Write a PNG chunk of `kind` with `data`
+pub(crate) fn write_chunk(mut writer: impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "PNG chunk too large"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc32(crc32(0, kind), data).to_be_bytes())
+}
+
+/// The IHDR chunk data for an 8-bit RGB image
+pub(crate) fn header(width: usize, height: usize) -> io::Result<Vec<u8>> {
+    let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "Image too large for PNG");
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&u32::try_from(width).map_err(too_large)?.to_be_bytes());
+    ihdr.extend_from_slice(&u32::try_from(height).map_err(too_large)?.to_be_bytes());
+    // bit depth, color type (RGB), compression, filter, interlace
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    Ok(ihdr)
+}
+
+/// Wrap `data` into a zlib stream of stored deflate blocks
+pub(crate) fn store(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // CMF (deflate, 32K window) and FLG (no dictionary, fastest), with FCHECK making it a multiple of 31
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut adler = Adler32::new();
+    let mut chunks = data.chunks(MAX_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        stream.push(last as u8);
+        stream.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        stream.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        stream.extend_from_slice(chunk);
+        adler.update(chunk);
+    }
+    stream.extend_from_slice(&adler.finish().to_be_bytes());
+    stream
+}
+
+/// The scanlines of `pixels` as unfiltered RGB, each prefixed by its filter type
+pub(crate) fn scanlines(pixels: &Pixels) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(pixels.height() * (pixels.width() * 3 + 1));
+    for y in 0..pixels.height() {
+        raw.push(0);
+        pixels.rgb_row(y).for_each(|rgb| raw.extend_from_slice(&rgb));
+    }
+    raw
+}
+
+/// Encode `pixels` as 8-bit RGB PNG with stored deflate blocks
+pub fn encode(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    writer.write_all(&SIGNATURE)?;
+    write_chunk(&mut writer, b"IHDR", &header(pixels.width(), pixels.height())?)?;
+    write_chunk(&mut writer, b"IDAT", &store(&scanlines(pixels)))?;
+    write_chunk(&mut writer, b"IEND", &[])
+}
+
+/// Reverse the scanline filter of `line`, with `previous` being the already unfiltered line above
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], bpp: usize) -> io::Result<()> {
+    fn paeth(a: u8, b: u8, c: u8) -> u8 {
+        let p = a as i16 + b as i16 - c as i16;
+        let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+        if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+    }
+    for i in 0..line.len() {
+        let left = if i >= bpp { line[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        line[i] = line[i].wrapping_add(match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(invalid("Invalid PNG filter type"))
+        });
+    }
+    Ok(())
+}
+
+/// Decode a PNG into RGB pixels, dropping transparency
+pub fn decode(data: &[u8]) -> io::Result<Decoded> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(invalid("Missing PNG signature"));
+    }
+    let mut chunks = &data[SIGNATURE.len()..];
+    let (mut ihdr, mut palette, mut idat) = (None, &[][..], Vec::new());
+    while chunks.len() >= 12 {
+        let length = u32::from_be_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as usize;
+        let kind = &chunks[4..8];
+        let body = chunks.get(8..8 + length).ok_or_else(|| invalid("Truncated PNG chunk"))?;
+        match kind {
+            b"IHDR" if body.len() == 13 => ihdr = Some(body),
+            b"PLTE" => palette = body,
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => ()
+        }
+        chunks = chunks.get(12 + length..).unwrap_or(&[]);
+    }
+
+    let ihdr = ihdr.ok_or_else(|| invalid("Missing PNG header"))?;
+    let width = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]) as usize;
+    let height = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]) as usize;
+    let (depth, color_type, interlace) = (ihdr[8] as usize, ihdr[9], ihdr[12]);
+    if interlace != 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Interlaced PNGs are not supported"));
+    }
+    let channels = match (color_type, depth) {
+        (0, 8 | 16) => 1,
+        (2, 8 | 16) => 3,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (6, 8 | 16) => 4,
+        _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported PNG color type or bit depth"))
+    };
+
+    let mut image = Decoded::new(width, height, idat.len())?;
+    let line_length = (width * channels * depth).div_ceil(8);
+    let raw = super::inflate::decompress(&idat, height * (line_length + 1))?;
+    let bpp = (channels * depth / 8).max(1);
+    if raw.len() < height * (line_length + 1) {
+        return Err(invalid("Truncated PNG image data"));
+    }
+
+    let mut previous = vec![0u8; line_length];
+    let mut line = vec![0u8; line_length];
+    for y in 0..height {
+        let filtered = &raw[y * (line_length + 1)..(y + 1) * (line_length + 1)];
+        line.copy_from_slice(&filtered[1..]);
+        unfilter(filtered[0], &mut line, &previous, bpp)?;
+
+        for x in 0..width {
+            // The most significant byte of each sample, or the palette index
+            let sample = |channel: usize| if depth == 16 { line[(x * channels + channel) * 2] } else { line[x * channels + channel] };
+            let rgb = match color_type {
+                0 | 4 => [sample(0); 3],
+                3 => {
+                    let bit = x * depth;
+                    let index = (line[bit / 8] >> (8 - depth - bit % 8)) as usize & ((1 << depth) - 1);
+                    let entry = palette.get(index * 3..index * 3 + 3).ok_or_else(|| invalid("PNG palette index out of range"))?;
+                    [entry[0], entry[1], entry[2]]
+                },
+                _ => [sample(0), sample(1), sample(2)]
+            };
+            image.data.extend_from_slice(&rgb);
+        }
+        std::mem::swap(&mut previous, &mut line);
+    }
+    Ok(image)
+}
+
+#[test]
+fn computes_checksums() {
+    assert_eq!(crc32(0, b"IEND"), 0xae42_6082);
+    let mut adler = Adler32::new();
+    adler.update(b"Wikipedia");
+    assert_eq!(adler.finish(), 0x11e6_0398);
+}
+
+#[test]
+fn decodes_its_own_output() {
+    let data = [0x30, 0x20, 0x10, 0, 0xff, 0xfe, 0xfd, 0, 1, 2, 3, 0, 4, 5, 6, 0];
+    let pixels = Pixels::new(&data, 2, 2, 8).unwrap();
+    let mut png = vec![];
+    encode(&mut png, &pixels).unwrap();
+    let decoded = decode(&png).unwrap();
+    assert_eq!((decoded.width, decoded.height), (2, 2));
+    assert_eq!(decoded.data, [0x10, 0x20, 0x30, 0xfd, 0xfe, 0xff, 3, 2, 1, 6, 5, 4]);
+}
+
+#[test]
+fn decodes_compressed_image_data() {
+    // zlib.compress(<8x4 RGB scanlines of (x * 32, y * 64, x * y * 8)>, 9), a dynamic huffman block
+    let idat = [
+        0x78, 0xda, 0x15, 0xcb, 0xa1, 0x15, 0x80, 0x30, 0x10, 0x04, 0xd1, 0x95, 0xc8, 0xc8, 0xc8, 0x93, 0xc8, 0x48,
+        0xe4, 0x4a, 0x24, 0x25, 0x5c, 0x09, 0x48, 0xe4, 0x96, 0x40, 0x09, 0x91, 0x94, 0xb1, 0xa5, 0xa4, 0x14, 0x12,
+        0xfb, 0xdf, 0x0c, 0x00, 0x04, 0x40, 0x20, 0x01, 0x01, 0x1d, 0x30, 0x30, 0xa6, 0x4e, 0x0b, 0x6e, 0x64, 0x49,
+        0x56, 0x31, 0x3a, 0x77, 0xb3, 0x0d, 0x1e, 0x2b, 0x0b, 0x15, 0x2a, 0x52, 0x4d, 0x62, 0xd7, 0x65, 0xe5, 0xd0,
+        0xbd, 0xce, 0x70, 0xa5, 0x5b, 0xfa, 0x94, 0xb3, 0xfb, 0xb1, 0xdf, 0xe1, 0xef, 0x07, 0x65, 0xe0, 0x1f, 0x41
+    ];
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header(8, 4).unwrap()).unwrap();
+    write_chunk(&mut png, b"IDAT", &idat).unwrap();
+    write_chunk(&mut png, b"IEND", &[]).unwrap();
+
+    let decoded = decode(&png).unwrap();
+    assert_eq!((decoded.width, decoded.height), (8, 4));
+    let expected: Vec<u8> = (0..4u8).flat_map(|y| (0..8u8).flat_map(move |x| [x * 32, y * 64, x * y * 8])).collect();
+    assert_eq!(decoded.data, expected);
+}
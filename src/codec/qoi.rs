@@ -0,0 +1,153 @@
+//!
+//! Encoder and decoder for the [Quite OK Image Format](https://qoiformat.org/qoi-specification.pdf).
+//!
+//! QOI compresses losslessly at a speed comparable to raw formats, which makes it a good fit for
+//! saving captures in rapid succession.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use super::{Decoded, Pixels, invalid};
+
+pub(crate) const MAGIC: &[u8; 4] = b"qoif";
+pub(crate) const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+pub(crate) const OP_INDEX: u8 = 0x00;
+pub(crate) const OP_DIFF: u8 = 0x40;
+pub(crate) const OP_LUMA: u8 = 0x80;
+pub(crate) const OP_RUN: u8 = 0xc0;
+pub(crate) const OP_RGB: u8 = 0xfe;
+pub(crate) const OP_RGBA: u8 = 0xff;
+
+pub(crate) fn hash(pixel: [u8; 4]) -> usize {
+    let [r, g, b, a] = pixel.map(|channel| channel as usize);
+    (r * 3 + g * 5 + b * 7 + a * 11) % 64
+}
+
+/// Encode `pixels` as QOI with three channels in the sRGB colorspace
+pub fn encode(mut writer: impl Write, pixels: &Pixels) -> io::Result<()> {
+    let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "Image too large for QOI");
+    let mut out = Vec::with_capacity(14 + pixels.width() * pixels.height() + 8);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&u32::try_from(pixels.width()).map_err(too_large)?.to_be_bytes());
+    out.extend_from_slice(&u32::try_from(pixels.height()).map_err(too_large)?.to_be_bytes());
+    out.extend_from_slice(&[3, 0]);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut previous = [0, 0, 0, 255];
+    let mut run = 0u8;
+
+    for y in 0..pixels.height() {
+        for [r, g, b] in pixels.rgb_row(y) {
+            let pixel = [r, g, b, 255];
+            if pixel == previous {
+                run += 1;
+                if run == 62 {
+                    out.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+
+            let position = hash(pixel);
+            if index[position] == pixel {
+                out.push(OP_INDEX | position as u8);
+            } else {
+                index[position] = pixel;
+                let dr = r.wrapping_sub(previous[0]) as i8;
+                let dg = g.wrapping_sub(previous[1]) as i8;
+                let db = b.wrapping_sub(previous[2]) as i8;
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+                if (-2..2).contains(&dr) && (-2..2).contains(&dg) && (-2..2).contains(&db) {
+                    out.push(OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+                } else if (-32..32).contains(&dg) && (-8..8).contains(&dr_dg) && (-8..8).contains(&db_dg) {
+                    out.push(OP_LUMA | (dg + 32) as u8);
+                    out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    out.extend_from_slice(&[OP_RGB, r, g, b]);
+                }
+            }
+            previous = pixel;
+        }
+    }
+    if run > 0 {
+        out.push(OP_RUN | (run - 1));
+    }
+    out.extend_from_slice(&END_MARKER);
+    writer.write_all(&out)
+}
+
+/// Decode a QOI image with three or four channels into RGB pixels, dropping transparency
+pub fn decode(data: &[u8]) -> io::Result<Decoded> {
+    if data.len() < 14 + END_MARKER.len() || !data.starts_with(MAGIC) {
+        return Err(invalid("Missing QOI header"));
+    }
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    let chunks = &data[14..data.len() - END_MARKER.len()];
+    // Runs can encode 62 pixels per byte
+    let mut image = Decoded::new(width, height, chunks.len().saturating_mul(62) / 1024 + 1)?;
+    let pixels = width * height;
+
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 255];
+    let mut position = 0;
+    let truncated = || invalid("Truncated QOI image data");
+    while image.data.len() < pixels * 3 {
+        let op = *chunks.get(position).ok_or_else(truncated)?;
+        position += 1;
+        let mut run = 1;
+        match op {
+            OP_RGB | OP_RGBA => {
+                let length = if op == OP_RGB { 3 } else { 4 };
+                let values = chunks.get(position..position + length).ok_or_else(truncated)?;
+                pixel[..length].copy_from_slice(values);
+                position += length;
+            },
+            _ => match op & 0xc0 {
+                OP_INDEX => pixel = index[op as usize],
+                OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add((op >> 4 & 3).wrapping_sub(2));
+                    pixel[1] = pixel[1].wrapping_add((op >> 2 & 3).wrapping_sub(2));
+                    pixel[2] = pixel[2].wrapping_add((op & 3).wrapping_sub(2));
+                },
+                OP_LUMA => {
+                    let second = *chunks.get(position).ok_or_else(truncated)?;
+                    position += 1;
+                    let dg = (op & 0x3f).wrapping_sub(32);
+                    pixel[0] = pixel[0].wrapping_add(dg.wrapping_add(second >> 4).wrapping_sub(8));
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(dg.wrapping_add(second & 0x0f).wrapping_sub(8));
+                },
+                _ => run = (op & 0x3f) as usize + 1
+            }
+        }
+        index[hash(pixel)] = pixel;
+        for _ in 0..run.min(pixels - image.data.len() / 3) {
+            image.data.extend_from_slice(&pixel[..3]);
+        }
+    }
+    Ok(image)
+}
+
+#[test]
+fn encodes_runs_diffs_and_indices() {
+    // black, black, (1, 0, 255), black, (1, 0, 255)
+    let data = [0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 1, 0, 0, 0, 0, 0, 255, 0, 1, 0];
+    let pixels = Pixels::new(&data, 5, 1, 20).unwrap();
+    let mut qoi = vec![];
+    encode(&mut qoi, &pixels).unwrap();
+    assert_eq!(&qoi[..14], b"qoif\0\0\0\x05\0\0\0\x01\x03\x00");
+    assert_eq!(&qoi[14..qoi.len() - 8], &[
+        OP_RUN | 1,
+        OP_DIFF | 3 << 4 | 2 << 2 | 1,
+        OP_DIFF | 1 << 4 | 2 << 2 | 3,
+        OP_INDEX | hash([1, 0, 255, 255]) as u8
+    ]);
+    assert_eq!(decode(&qoi).unwrap().data, [0, 0, 0, 0, 0, 0, 1, 0, 255, 0, 0, 0, 1, 0, 255]);
+}
@@ -0,0 +1,90 @@
+//!
+//! Exposes the X11 `DAMAGE` extension.
+//!
+//! A [`Damage`] object makes the server report every change of the screen contents
+//! as an [`Event::Damage`](crate::event::Event::Damage), e.g. to capture the screen only once it changed.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, event::Event};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     if let Ok(_damage) = display.watch_damage() {
+//!         # if false {
+//!         if let Event::Damage { area, .. } = display.next_event() {
+//!             println!("{:?} changed", area);
+//!         }
+//!         # }
+//!     }
+//! }
+//! ```
+//!
+
+use crate::{Display, ffi::{XID, damage::{XDamageQueryExtension, XDamageCreate, XDamageDestroy, XDamageReportRawRectangles}}};
+
+/// Reports the changes of the screen contents as [`Event::Damage`](crate::event::Event::Damage), until it is dropped.
+pub struct Damage<'a> {
+    display: &'a Display,
+    damage: XID
+}
+
+impl Damage<'_> {
+    /// The id of the damage object, as reported in the `damage` field of the events
+    pub fn id(&self) -> XID {
+        self.damage
+    }
+}
+
+impl Drop for Damage<'_> {
+    fn drop(&mut self) {
+        unsafe { XDamageDestroy(self.display.connection, self.damage) };
+    }
+}
+
+impl Display {
+    /// Report every change of the screen contents as [`Event::Damage`](crate::event::Event::Damage),
+    /// with the changed rectangle.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the server lacks the `DAMAGE` extension or, with the `dlopen` feature, if `libXdamage.so.1` couldn't be loaded.
+    #[allow(clippy::result_unit_err)]
+    pub fn watch_damage(&self) -> Result<Damage<'_>, ()> {
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XDAMAGE.available() {
+            return Err(());
+        }
+        // Also initializes the extension in Xlib, which only then translates its events
+        let (mut event_base, mut error_base) = (0, 0);
+        if unsafe { XDamageQueryExtension(self.connection, &mut event_base, &mut error_base) } == 0 {
+            return Err(());
+        }
+        let damage = unsafe { XDamageCreate(self.connection, self.window, XDamageReportRawRectangles) };
+        Ok(Damage { display: self, damage })
+    }
+}
+
+#[test]
+fn reports_changes_of_the_root_window() {
+    use crate::{event::Event, ffi::{XCreateGC, XFillRectangle, XFreeGC, XSync}};
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let damage = display.watch_damage().unwrap();
+    unsafe {
+        let gc = XCreateGC(display.connection, display.window, 0, std::ptr::null());
+        XFillRectangle(display.connection, display.window, gc, 10, 20, 30, 40);
+        XFreeGC(display.connection, gc);
+        XSync(display.connection, 0);
+    }
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        match display.poll_event() {
+            Some(Event::Damage { damage: id, drawable, area, .. }) => {
+                assert_eq!((id, drawable), (damage.id(), display.window));
+                assert_eq!(area, (10, 20, 30, 40));
+                return;
+            },
+            Some(_) => {},
+            None => std::thread::sleep(std::time::Duration::from_millis(10))
+        }
+    }
+    panic!("No damage reported");
+}
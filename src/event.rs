@@ -0,0 +1,243 @@
+//!
+//! Safe access to the events sent by the X server.
+//!
+//! Events are read from the connection of a [`Display`] through [`Display::next_event`],
+//! [`Display::poll_event`] and [`Display::pending`] and are translated from the raw `XEvent` union
+//! into the [`Event`] enum.
+//!
+//! Besides the core protocol events, completion events of the `MIT-SHM` extension, as well as
+//! `RandR` and `DAMAGE` notifications are recognized. The server sends the latter two once requested through
+//! `Display::watch_screen_changes` (`xrandr` feature) and `Display::watch_damage` (`damage` feature).
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, event::Event};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     while let Some(event) = display.poll_event() {
+//!         match event {
+//!             Event::KeyPress { keycode, .. } => println!("Key {} pressed", keycode),
+//!             Event::Expose { window, .. } => println!("Window {} needs a redraw", window),
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+#![allow(non_upper_case_globals)]
+
+use core::ffi::{c_char, c_int, c_long, c_ulong};
+use crate::{Display, ffi::{*, constants::{KeyPress, KeyRelease, ButtonPress, ButtonRelease, MotionNotify, Expose, ConfigureNotify,
+    MapNotify, UnmapNotify, DestroyNotify, PropertyNotify, PropertyDelete, ClientMessage}, events::{XEvent, XShmCompletionEvent, XRRScreenChangeNotifyEvent, XRRNotifyEvent, XDamageNotifyEvent}}};
+
+/// The events sent by the X server
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    KeyPress { window: XWindow, keycode: u32, state: u32, x: i32, y: i32, time: Time },
+    KeyRelease { window: XWindow, keycode: u32, state: u32, x: i32, y: i32, time: Time },
+    ButtonPress { window: XWindow, button: u32, state: u32, x: i32, y: i32, time: Time },
+    ButtonRelease { window: XWindow, button: u32, state: u32, x: i32, y: i32, time: Time },
+    /// The pointer moved, `x` and `y` are relative to `window`, `x_root` and `y_root` to the root window.
+    Motion { window: XWindow, x: i32, y: i32, x_root: i32, y_root: i32, state: u32, time: Time },
+    /// The area (`x`, `y`, `width`, `height`) of `window` has to be redrawn,
+    /// `count` is the number of `Expose` events that are still to follow.
+    Expose { window: XWindow, x: i32, y: i32, width: i32, height: i32, count: i32 },
+    /// `window` changed its position, size or border width.
+    Configure { window: XWindow, x: i32, y: i32, width: i32, height: i32, border_width: i32 },
+    Map { window: XWindow },
+    Unmap { window: XWindow },
+    Destroy { window: XWindow },
+    /// The property `atom` of `window` changed, `deleted` is set if the property got removed.
+    Property { window: XWindow, atom: Atom, deleted: bool, time: Time },
+    /// A message sent by another client, e.g. the window manager's `WM_PROTOCOLS`.
+    ClientMessage { window: XWindow, message_type: Atom, format: i32, data: [c_long; 5] },
+    /// A `XShmPutImage` request finished and the segment `shmseg` can be reused.
+    ShmCompletion { drawable: XID, shmseg: c_ulong, offset: c_ulong },
+    /// The configuration of the screen changed (RandR).
+    ScreenChange { root: XWindow, width: i32, height: i32, mwidth: i32, mheight: i32, rotation: u16 },
+    /// A RandR notification about a CRTC, output or provider change, see `RRNotify_*` for the `subtype`.
+    RandrNotify { window: XWindow, subtype: i32 },
+    /// The contents of `drawable` changed in `area` (DAMAGE).
+    Damage { drawable: XID, damage: XID, area: (i32, i32, u32, u32), more: bool, time: Time },
+    /// Any event not covered by the variants above, `kind` is the raw event type.
+    Other { kind: i32 }
+}
+
+impl Event {
+    /// The window (or drawable) the event was reported for, if any.
+    pub fn window(&self) -> Option<XWindow> {
+        match *self {
+            Event::KeyPress { window, .. } | Event::KeyRelease { window, .. }
+            | Event::ButtonPress { window, .. } | Event::ButtonRelease { window, .. }
+            | Event::Motion { window, .. } | Event::Expose { window, .. }
+            | Event::Configure { window, .. } | Event::Map { window }
+            | Event::Unmap { window } | Event::Destroy { window }
+            | Event::Property { window, .. } | Event::ClientMessage { window, .. }
+            | Event::RandrNotify { window, .. } => Some(window),
+            Event::ShmCompletion { drawable, .. } | Event::Damage { drawable, .. } => Some(drawable),
+            Event::ScreenChange { root, .. } => Some(root),
+            Event::Other { .. } => None
+        }
+    }
+}
+
+/// First event codes of the extensions recognized by [`Event`], `None` if the server lacks the extension.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct ExtensionEvents {
+    pub(crate) shm: Option<i32>,
+    pub(crate) randr: Option<i32>,
+    pub(crate) damage: Option<i32>
+}
+
+impl ExtensionEvents {
+    fn query(display: &Display) -> Self {
+        let base = |name: &[u8]| {
+            let (mut opcode, mut event, mut error): (c_int, c_int, c_int) = (0, 0, 0);
+            let present = unsafe { XQueryExtension(display.connection, name.as_ptr() as *const c_char, &mut opcode, &mut event, &mut error) };
+            if present != 0 { Some(event) } else { None }
+        };
+        Self {
+            shm: base(b"MIT-SHM\0"),
+            randr: base(b"RANDR\0"),
+            damage: base(b"DAMAGE\0")
+        }
+    }
+}
+
+/// `RRNotify` subtypes
+pub const RRNotify_CrtcChange: i32 = 0;
+pub const RRNotify_OutputChange: i32 = 1;
+pub const RRNotify_OutputProperty: i32 = 2;
+pub const RRNotify_ProviderChange: i32 = 3;
+pub const RRNotify_ProviderProperty: i32 = 4;
+pub const RRNotify_ResourceChange: i32 = 5;
+
+impl Event {
+    pub(crate) unsafe fn from_raw(xev: &XEvent, extensions: &ExtensionEvents) -> Self {
+        let kind = xev.r#type;
+        match kind as u32 {
+            KeyPress | KeyRelease => {
+                let e = xev.xkey;
+                if kind as u32 == KeyPress {
+                    Event::KeyPress { window: e.window, keycode: e.keycode, state: e.state, x: e.x, y: e.y, time: e.time }
+                } else {
+                    Event::KeyRelease { window: e.window, keycode: e.keycode, state: e.state, x: e.x, y: e.y, time: e.time }
+                }
+            },
+            ButtonPress | ButtonRelease => {
+                let e = xev.xbutton;
+                if kind as u32 == ButtonPress {
+                    Event::ButtonPress { window: e.window, button: e.button, state: e.state, x: e.x, y: e.y, time: e.time }
+                } else {
+                    Event::ButtonRelease { window: e.window, button: e.button, state: e.state, x: e.x, y: e.y, time: e.time }
+                }
+            },
+            MotionNotify => {
+                let e = xev.xmotion;
+                Event::Motion { window: e.window, x: e.x, y: e.y, x_root: e.x_root, y_root: e.y_root, state: e.state, time: e.time }
+            },
+            Expose => {
+                let e = xev.xexpose;
+                Event::Expose { window: e.window, x: e.x, y: e.y, width: e.width, height: e.height, count: e.count }
+            },
+            ConfigureNotify => {
+                let e = xev.xconfigure;
+                Event::Configure { window: e.window, x: e.x, y: e.y, width: e.width, height: e.height, border_width: e.border_width }
+            },
+            MapNotify => Event::Map { window: xev.xmap.window },
+            UnmapNotify => Event::Unmap { window: xev.xunmap.window },
+            DestroyNotify => Event::Destroy { window: xev.xdestroywindow.window },
+            PropertyNotify => {
+                let e = xev.xproperty;
+                Event::Property { window: e.window, atom: e.atom, deleted: e.state == PropertyDelete as i32, time: e.time }
+            },
+            ClientMessage => {
+                let e = xev.xclient;
+                Event::ClientMessage { window: e.window, message_type: e.message_type, format: e.format, data: e.data.l }
+            },
+            _ => Self::from_extension(xev, extensions)
+        }
+    }
+
+    unsafe fn from_extension(xev: &XEvent, extensions: &ExtensionEvents) -> Self {
+        let kind = xev.r#type;
+        let event = xev as *const XEvent;
+        if extensions.shm == Some(kind) {
+            let e = &*(event as *const XShmCompletionEvent);
+            Event::ShmCompletion { drawable: e.drawable, shmseg: e.shmseg, offset: e.offset }
+        } else if extensions.randr == Some(kind) {
+            let e = &*(event as *const XRRScreenChangeNotifyEvent);
+            Event::ScreenChange { root: e.root, width: e.width, height: e.height, mwidth: e.mwidth, mheight: e.mheight, rotation: e.rotation }
+        } else if extensions.randr.map(|base| base + 1) == Some(kind) {
+            let e = &*(event as *const XRRNotifyEvent);
+            Event::RandrNotify { window: e.window, subtype: e.subtype }
+        } else if extensions.damage == Some(kind) {
+            let e = &*(event as *const XDamageNotifyEvent);
+            Event::Damage {
+                drawable: e.drawable,
+                damage: e.damage,
+                area: (e.area.x as i32, e.area.y as i32, e.area.width as u32, e.area.height as u32),
+                more: e.more != 0,
+                time: e.timestamp
+            }
+        } else {
+            Event::Other { kind }
+        }
+    }
+}
+
+impl Display {
+    pub(crate) fn extension_events(&self) -> &ExtensionEvents {
+        self.extensions.get_or_init(|| ExtensionEvents::query(self))
+    }
+
+    /// Wait for the next event and return it.
+    ///
+    /// This function blocks until an event is received.
+    /// ```rust
+    /// # use rxscreen::{Display, event::Event};
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     # if false {
+    ///     let event = display.next_event();
+    ///     # }
+    /// }
+    /// ```
+    pub fn next_event(&self) -> Event {
+        let extensions = *self.extension_events();
+        unsafe {
+            let mut xev = XEvent { r#type: 0 };
+            XNextEvent(self.connection, &mut xev);
+            Event::from_raw(&xev, &extensions)
+        }
+    }
+
+    /// Return the next event if one has already been received, without blocking.
+    pub fn poll_event(&self) -> Option<Event> {
+        if self.pending() > 0 {
+            Some(self.next_event())
+        } else {
+            None
+        }
+    }
+
+    /// The number of events that have been received but not yet read.
+    ///
+    /// Outstanding requests are flushed to the X server by this call.
+    pub fn pending(&self) -> usize {
+        unsafe { XPending(self.connection) as usize }
+    }
+}
+
+#[test]
+fn translates_client_messages() {
+    let mut xev = XEvent { pad: [0; 24] };
+    xev.xclient.type_ = ClientMessage as i32;
+    xev.xclient.window = 42;
+    xev.xclient.message_type = 7;
+    xev.xclient.format = 32;
+    unsafe {
+        xev.xclient.data.l[0] = 1234;
+        let event = Event::from_raw(&xev, &ExtensionEvents::default());
+        assert_eq!(event, Event::ClientMessage { window: 42, message_type: 7, format: 32, data: [1234, 0, 0, 0, 0] });
+        assert_eq!(event.window(), Some(42));
+    }
+}
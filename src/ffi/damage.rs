@@ -0,0 +1,18 @@
+use core::ffi::c_int;
+
+use crate::ffi::*;
+
+library! {
+    XDAMAGE;
+    pub(crate) fn XDamageQueryExtension(display: XDisplay, event_base: *mut c_int, error_base: *mut c_int) -> c_int /* Bool */;
+    pub(crate) fn XDamageCreate(display: XDisplay, drawable: Drawable, level: c_int) -> Damage;
+    pub(crate) fn XDamageDestroy(display: XDisplay, damage: Damage);
+}
+
+pub type Damage = XID;
+
+/// Report levels of `XDamageCreate`
+pub(crate) const XDamageReportRawRectangles: c_int = 0;
+pub(crate) const XDamageReportDeltaRectangles: c_int = 1;
+pub(crate) const XDamageReportBoundingBox: c_int = 2;
+pub(crate) const XDamageReportNonEmpty: c_int = 3;
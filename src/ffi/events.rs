@@ -1,4 +1,4 @@
-use core::ffi::{c_char, c_int, c_long, c_short, c_uchar, c_uint, c_ulong, c_void};
+use core::ffi::{c_char, c_int, c_long, c_short, c_uchar, c_uint, c_ulong, c_ushort, c_void};
 use super::{XDisplay, XWindow, Time, Drawable, Atom, Colormap, XID};
 
 pub const KeyPress: u32 = 2;
@@ -518,6 +518,77 @@ pub struct XGenericEventCookie {
     pub data: *mut c_void,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XRectangle {
+    pub x: c_short,
+    pub y: c_short,
+    pub width: c_ushort,
+    pub height: c_ushort,
+}
+
+/// `MIT-SHM` completion event, sent after a `XShmPutImage` with `send_event` set.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XShmCompletionEvent {
+    pub type_: c_int,
+    pub serial: c_ulong,
+    pub send_event: c_int,
+    pub display: *mut XDisplay,
+    pub drawable: Drawable,
+    pub major_code: c_int,
+    pub minor_code: c_int,
+    pub shmseg: c_ulong,
+    pub offset: c_ulong,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XRRScreenChangeNotifyEvent {
+    pub type_: c_int,
+    pub serial: c_ulong,
+    pub send_event: c_int,
+    pub display: *mut XDisplay,
+    pub window: XWindow,
+    pub root: XWindow,
+    pub timestamp: Time,
+    pub config_timestamp: Time,
+    pub size_index: c_ushort,
+    pub subpixel_order: c_ushort,
+    pub rotation: c_ushort,
+    pub width: c_int,
+    pub height: c_int,
+    pub mwidth: c_int,
+    pub mheight: c_int,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XRRNotifyEvent {
+    pub type_: c_int,
+    pub serial: c_ulong,
+    pub send_event: c_int,
+    pub display: *mut XDisplay,
+    pub window: XWindow,
+    pub subtype: c_int,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XDamageNotifyEvent {
+    pub type_: c_int,
+    pub serial: c_ulong,
+    pub send_event: c_int,
+    pub display: *mut XDisplay,
+    pub drawable: Drawable,
+    pub damage: XID,
+    pub level: c_int,
+    pub more: c_int,
+    pub timestamp: Time,
+    pub area: XRectangle,
+    pub geometry: XRectangle,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub union XEvent {
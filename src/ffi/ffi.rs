@@ -1,6 +1,6 @@
-use core::ffi::{c_char, c_int, c_long, c_uint, c_ulong};
+use core::ffi::{c_char, c_int, c_long, c_short, c_uint, c_ulong, c_void};
 
-use super::events::XEvent;
+use super::events::{XEvent, XErrorEvent};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -24,6 +24,7 @@ pub type Colormap = XID;
 pub type GContext = XID;
 pub type KeySym = XID;
 pub type XPointer = *mut c_char;
+pub type XErrorHandler = Option<unsafe extern "C" fn(display: XDisplay, event: *mut XErrorEvent) -> c_int>;
 
 #[cfg(target_arch = "x86_64")]
 pub type CARD32 = c_uint;
@@ -32,7 +33,9 @@ pub type CARD32 = c_long;
 
 
 
-extern "C" {
+library! {
+	X11;
+	pub fn XInitThreads() -> c_int;
 	pub fn XOpenDisplay(display: *const c_char) -> XDisplay;
 	pub fn XDefaultRootWindow(display: XDisplay) -> XWindow;
 	pub fn XGetGeometry(
@@ -47,6 +50,10 @@ extern "C" {
 		depth_return: &mut c_uint);
 	pub fn XCloseDisplay(display: XDisplay);
 	pub fn XGetImage(display: XDisplay, d: XWindow, x: c_int, y: c_int, width: c_uint, height: c_uint, plane_mask: c_ulong, format: c_int) -> *mut XImage;
+	pub fn XGetSubImage(display: XDisplay, d: XWindow, x: c_int, y: c_int, width: c_uint, height: c_uint, plane_mask: c_ulong, format: c_int, dest_image: *const XImage, dest_x: c_int, dest_y: c_int) -> *mut XImage;
+	pub fn XConnectionNumber(display: XDisplay) -> c_int;
+	pub fn XSetErrorHandler(handler: XErrorHandler) -> XErrorHandler;
+    pub fn XInitImage(image: *mut XImage) -> c_int;
     pub fn XCreateImage(display: XDisplay, visual: *const Visual, depth: c_uint, format: c_int, offset: c_int, data: *const c_char, width: c_uint, height: c_uint, bitmap_pad: c_int, bytes_per_line: c_int) -> *mut XImage;
     
 	pub fn XDestroyImage(image: *const XImage);
@@ -68,8 +75,31 @@ extern "C" {
     pub fn XFlush(display: XDisplay) -> c_int;
     pub fn XSelectInput(display: XDisplay, window: XWindow, event_mask: c_ulong) -> c_int;
     pub fn XNextEvent(display: XDisplay, event: *mut XEvent) -> c_int;
+    pub fn XPending(display: XDisplay) -> c_int;
+    pub fn XIfEvent(display: XDisplay, event: *mut XEvent, predicate: unsafe extern "C" fn(display: XDisplay, event: *mut XEvent, arg: XPointer) -> c_int, arg: XPointer) -> c_int;
+    pub fn XQueryExtension(display: XDisplay, name: *const c_char, major_opcode_return: *mut c_int, first_event_return: *mut c_int, first_error_return: *mut c_int) -> c_int /* bool */;
     pub fn XSync(display: XDisplay, discard: c_int /* bool */) -> c_int;
+    pub fn XNextRequest(display: XDisplay) -> c_ulong;
+    pub fn XGrabServer(display: XDisplay) -> c_int;
+    pub fn XUngrabServer(display: XDisplay) -> c_int;
     pub fn XDrawString(display: XDisplay, drawable: Drawable, gc: GC, x: c_int, y: c_int, string: *const c_char, strlen: c_int) -> c_int;
+    pub fn XStoreName(display: XDisplay, window: XWindow, window_name: *const c_char) -> c_int;
+    pub fn XInternAtom(display: XDisplay, atom_name: *const c_char, only_if_exists: c_int /* bool */) -> Atom;
+    pub fn XSetWMProtocols(display: XDisplay, window: XWindow, protocols: *const Atom, count: c_int) -> c_int;
+    pub fn XResizeWindow(display: XDisplay, window: XWindow, width: c_uint, height: c_uint) -> c_int;
+    pub fn XMoveWindow(display: XDisplay, window: XWindow, x: c_int, y: c_int) -> c_int;
+    pub fn XDestroyWindow(display: XDisplay, window: XWindow) -> c_int;
+    pub fn XFreeGC(display: XDisplay, gc: GC) -> c_int;
+    pub fn XSetForeground(display: XDisplay, gc: GC, foreground: c_ulong) -> c_int;
+    pub fn XSetBackground(display: XDisplay, gc: GC, background: c_ulong) -> c_int;
+    pub fn XSetLineAttributes(display: XDisplay, gc: GC, line_width: c_uint, line_style: c_int, cap_style: c_int, join_style: c_int) -> c_int;
+    pub fn XDrawLine(display: XDisplay, drawable: Drawable, gc: GC, x1: c_int, y1: c_int, x2: c_int, y2: c_int) -> c_int;
+    pub fn XDrawLines(display: XDisplay, drawable: Drawable, gc: GC, points: *const XPoint, npoints: c_int, mode: c_int) -> c_int;
+    pub fn XDrawRectangle(display: XDisplay, drawable: Drawable, gc: GC, x: c_int, y: c_int, width: c_uint, height: c_uint) -> c_int;
+    pub fn XFillRectangle(display: XDisplay, drawable: Drawable, gc: GC, x: c_int, y: c_int, width: c_uint, height: c_uint) -> c_int;
+    pub fn XDrawArc(display: XDisplay, drawable: Drawable, gc: GC, x: c_int, y: c_int, width: c_uint, height: c_uint, angle1: c_int, angle2: c_int) -> c_int;
+    pub fn XFillArc(display: XDisplay, drawable: Drawable, gc: GC, x: c_int, y: c_int, width: c_uint, height: c_uint, angle1: c_int, angle2: c_int) -> c_int;
+    pub fn XFillPolygon(display: XDisplay, drawable: Drawable, gc: GC, points: *const XPoint, npoints: c_int, shape: c_int, mode: c_int) -> c_int;
 }
 
 
@@ -91,7 +121,27 @@ pub struct XImage {
     pub red_mask: c_ulong,
     pub green_mask: c_ulong,
     pub blue_mask: c_ulong,
-    pub obdata: XPointer
+    pub obdata: XPointer,
+    pub f: XImageFunctions
+}
+
+/// The functions of an `XImage`, filled in by `XInitImage` and `XCreateImage`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct XImageFunctions {
+    pub create_image: *const c_void,
+    pub destroy_image: *const c_void,
+    pub get_pixel: *const c_void,
+    pub put_pixel: *const c_void,
+    pub sub_image: *const c_void,
+    pub add_pixel: *const c_void
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct XPoint {
+    pub x: c_short,
+    pub y: c_short,
 }
 
 #[derive(Debug, PartialEq)]
@@ -158,7 +208,7 @@ pub type VisualID = CARD32;
 pub struct XExtData {
     number: c_int,
     next: *const XExtData,
-    free_private: fn(extension: *const XExtData) -> c_int,
+    free_private: *const c_void, // int (*free_private)(XExtData *extension)
     private_data: *const char
 }
 
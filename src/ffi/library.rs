@@ -0,0 +1,94 @@
+//! Binding the functions of the X libraries, either at link time or, with the `dlopen` feature, at runtime.
+
+use core::ffi::{c_char, c_void};
+#[cfg(feature = "dlopen")]
+use std::sync::OnceLock;
+
+/// Declare the functions of `$library`.
+///
+/// By default they are declared `extern "C"`, so the library is linked (see `build.rs`).
+/// With the `dlopen` feature, each is a wrapper looking up the function on its first call instead,
+/// which panics if the library or the function is missing. Callers check [`Library::available`] first.
+macro_rules! library {
+    ($library:ident; $($vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        #[cfg(not(feature = "dlopen"))]
+        extern "C" {
+            $($vis fn $name($($arg: $ty),*) $(-> $ret)?;)*
+        }
+        $(
+            #[cfg(feature = "dlopen")]
+            #[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+            $vis unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                static SYMBOL: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+                let address = *SYMBOL.get_or_init(|| $crate::ffi::library::$library.symbol(concat!(stringify!($name), "\0")));
+                let function: unsafe extern "C" fn($($ty),*) $(-> $ret)? = std::mem::transmute(address as *const core::ffi::c_void);
+                function($($arg),*)
+            }
+        )*
+    };
+}
+
+/// A shared library, loaded on first use
+#[cfg(feature = "dlopen")]
+pub(crate) struct Library {
+    /// The soname, nul terminated
+    name: &'static str,
+    handle: OnceLock<Option<usize>>
+}
+
+#[cfg(feature = "dlopen")]
+pub(crate) static X11: Library = Library::new("libX11.so.6\0");
+#[cfg(all(feature = "dlopen", feature = "shm"))]
+pub(crate) static XEXT: Library = Library::new("libXext.so.6\0");
+#[cfg(all(feature = "dlopen", feature = "xrandr"))]
+pub(crate) static XRANDR: Library = Library::new("libXrandr.so.2\0");
+#[cfg(all(feature = "dlopen", feature = "damage"))]
+pub(crate) static XDAMAGE: Library = Library::new("libXdamage.so.1\0");
+
+#[cfg(feature = "dlopen")]
+impl Library {
+    const fn new(name: &'static str) -> Self {
+        Self { name, handle: OnceLock::new() }
+    }
+    fn handle(&self) -> Option<*mut c_void> {
+        let handle = self.handle.get_or_init(|| {
+            // Lets tests pretend a library isn't installed
+            #[cfg(test)]
+            if std::env::var_os("RXSCREEN_MISSING_LIBRARY").is_some_and(|name| name == self.name()) {
+                return None;
+            }
+            let handle = unsafe { libc::dlopen(self.name.as_ptr() as *const c_char, libc::RTLD_NOW | libc::RTLD_GLOBAL) };
+            (!handle.is_null()).then_some(handle as usize)
+        });
+        handle.map(|handle| handle as *mut c_void)
+    }
+    /// Whether the library could be loaded
+    pub(crate) fn available(&self) -> bool {
+        self.handle().is_some()
+    }
+    /// The soname of the library
+    pub(crate) fn name(&self) -> &'static str {
+        self.name.trim_end_matches('\0')
+    }
+    /// The address of the function `name`, which has to be nul terminated
+    pub(crate) fn symbol(&self, name: &str) -> usize {
+        let handle = self.handle().unwrap_or_else(|| panic!("{} couldn't be loaded", self.name()));
+        let address = unsafe { symbol::<*mut c_void>(handle, name.as_bytes()) };
+        address.unwrap_or_else(|| panic!("{} is missing from {}", name.trim_end_matches('\0'), self.name())) as usize
+    }
+}
+
+/// Look up the function `name` in `library`, `F` has to be its function pointer type
+pub(crate) unsafe fn symbol<F: Copy>(library: *mut c_void, name: &[u8]) -> Option<F> {
+    assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+    let address = libc::dlsym(library, name.as_ptr() as *const c_char);
+    (!address.is_null()).then(|| std::mem::transmute_copy(&address))
+}
+
+#[cfg(feature = "dlopen")]
+#[test]
+fn resolves_functions_at_runtime() {
+    assert!(!Library::new("libmissing.so.0\0").available());
+    assert!(X11.available());
+    assert_ne!(unsafe { super::XInitThreads() }, 0);
+}
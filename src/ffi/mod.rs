@@ -1,4 +1,6 @@
 #![allow(non_snake_case, non_camel_case_types, non_upper_case_globals, dead_code, improper_ctypes, clippy::module_inception)]
+#[macro_use]
+pub(crate) mod library;
 mod ffi;
 pub mod constants;
 pub mod events;
@@ -12,6 +14,9 @@ pub use shm::*;
 #[cfg(feature = "xrandr")]
 pub mod xrandr;
 
+#[cfg(feature = "damage")]
+pub mod damage;
+
 #[cfg(feature = "mouse")]
 pub mod mouse;
 
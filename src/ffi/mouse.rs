@@ -2,16 +2,17 @@ use core::ffi::{c_int, c_uint};
 
 use crate::ffi::*;
 
-extern "C" {
+library! {
+    X11;
     pub fn XQueryPointer(
         display: XDisplay,
         w: XWindow,
-        root_return: *const XWindow,
-        child_return: *const XWindow,
-        root_x_return: *const c_int,
-        root_y_return: *const c_int,
-        win_x_return: *const c_int,
-        win_y_return: *const c_int,
-        mask_return: *const c_uint,
+        root_return: *mut XWindow,
+        child_return: *mut XWindow,
+        root_x_return: *mut c_int,
+        root_y_return: *mut c_int,
+        win_x_return: *mut c_int,
+        win_y_return: *mut c_int,
+        mask_return: *mut c_uint,
     ) -> c_int;
 }
@@ -1,14 +1,19 @@
-use core::ffi::{c_uint, c_int, c_char, c_ulong};
+use core::ffi::{c_uint, c_int, c_char, c_ulong, c_void};
+use std::sync::OnceLock;
 
 use crate::ffi::*;
+use super::library::symbol;
 
-extern "C" {
+library! {
+    XEXT;
     pub fn XShmQueryExtension(display: XDisplay) -> bool;
+    pub fn XShmQueryVersion(display: XDisplay, major: *mut c_int, minor: *mut c_int, pixmaps: *mut c_int /* Bool */) -> c_int;
     //pub fn XShmCreateImage(display: XDisplay, visual: *const Visual, depth: c_uint, format: c_int, data: *const c_char, shminfo: *const XShmSegmentInfo, width: c_uint, height: c_uint);
     pub fn XShmCreateImage(display: XDisplay, visual: *const Visual, depth: c_uint, format: c_int, data: *const c_char, shminfo: *const XShmSegmentInfo, width: c_uint, height: c_uint) -> *const XImage;
     pub fn XShmAttach(display: XDisplay, shminfo: *const XShmSegmentInfo) -> c_int;
     pub fn XShmGetImage(display: XDisplay, drawable: XWindow, image: *const XImage, x: c_int, y: c_int, plane_mask: c_ulong) -> c_int;
     pub fn XShmDetach(display: XDisplay, shminfo: *const XShmSegmentInfo) -> c_int;
+    pub fn XShmPutImage(display: XDisplay, drawable: XWindow, gc: GC, image: *const XImage, src_x: c_int, src_y: c_int, dst_x: c_int, dst_y: c_int, src_width: c_uint, src_height: c_uint, send_event: c_int /* bool */) -> c_int;
 }
 
 
@@ -26,4 +31,42 @@ pub struct XShmSegmentInfo {
     pub read_only: c_int
 }
 
+/// `xcb_void_cookie_t`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct XcbVoidCookie {
+    pub sequence: c_uint
+}
+
+/// The XCB functions to attach file descriptors through MIT-SHM 1.2, which Xlib lacks.
+///
+/// They are loaded at runtime, so `libX11-xcb` and `libxcb-shm` remain optional.
+pub struct XcbShm {
+    pub get_connection: unsafe extern "C" fn(display: XDisplay) -> *mut c_void,
+    pub generate_id: unsafe extern "C" fn(connection: *mut c_void) -> u32,
+    pub attach_fd_checked: unsafe extern "C" fn(connection: *mut c_void, shmseg: u32, fd: i32, read_only: u8) -> XcbVoidCookie,
+    /// Returns an error, to be released with `free`, or null
+    pub request_check: unsafe extern "C" fn(connection: *mut c_void, cookie: XcbVoidCookie) -> *mut c_void
+}
+
+impl XcbShm {
+    /// The functions, if every library could be loaded
+    pub fn get() -> Option<&'static XcbShm> {
+        static XCB: OnceLock<Option<XcbShm>> = OnceLock::new();
+        XCB.get_or_init(|| unsafe { Self::load() }).as_ref()
+    }
 
+    unsafe fn load() -> Option<XcbShm> {
+        let open = |name: &[u8]| {
+            let library = libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_NOW | libc::RTLD_LOCAL);
+            (!library.is_null()).then_some(library)
+        };
+        let (x11_xcb, xcb, shm) = (open(b"libX11-xcb.so.1\0")?, open(b"libxcb.so.1\0")?, open(b"libxcb-shm.so.0\0")?);
+        Some(XcbShm {
+            get_connection: symbol(x11_xcb, b"XGetXCBConnection\0")?,
+            generate_id: symbol(xcb, b"xcb_generate_id\0")?,
+            attach_fd_checked: symbol(shm, b"xcb_shm_attach_fd_checked\0")?,
+            request_check: symbol(xcb, b"xcb_request_check\0")?
+        })
+    }
+}
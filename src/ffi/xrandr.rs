@@ -1,15 +1,45 @@
-use core::ffi::c_int;
+use core::ffi::{c_char, c_int, c_uint, c_ulong, c_ushort};
 
 use crate::ffi::*;
 
 
-extern "C" {
+library! {
+    XRANDR;
     pub(crate) fn XRRGetMonitors(display: XDisplay, window: XWindow, get_active: c_int /*Bool*/, nmonitors: *mut c_int) -> *const XRRMonitorInfo;
     pub(crate) fn XRRFreeMonitors(monitors: *const XRRMonitorInfo);
+    pub(crate) fn XRRSetMonitor(display: XDisplay, window: XWindow, monitor: *const XRRMonitorInfo);
+    pub(crate) fn XRRGetScreenResourcesCurrent(display: XDisplay, window: XWindow) -> *mut XRRScreenResources;
+    pub(crate) fn XRRFreeScreenResources(resources: *mut XRRScreenResources);
+    pub(crate) fn XRRGetOutputInfo(display: XDisplay, resources: *mut XRRScreenResources, output: RROutput) -> *mut XRROutputInfo;
+    pub(crate) fn XRRFreeOutputInfo(info: *mut XRROutputInfo);
+    pub(crate) fn XRRGetCrtcInfo(display: XDisplay, resources: *mut XRRScreenResources, crtc: RRCrtc) -> *mut XRRCrtcInfo;
+    pub(crate) fn XRRFreeCrtcInfo(info: *mut XRRCrtcInfo);
+    pub(crate) fn XRRGetOutputPrimary(display: XDisplay, window: XWindow) -> RROutput;
+    pub(crate) fn XRRSetOutputPrimary(display: XDisplay, window: XWindow, output: RROutput);
+    pub(crate) fn XRRSetCrtcConfig(display: XDisplay, resources: *mut XRRScreenResources, crtc: RRCrtc, timestamp: Time, x: c_int, y: c_int, mode: RRMode, rotation: Rotation, outputs: *const RROutput, noutputs: c_int) -> c_int /* Status */;
+    pub(crate) fn XRRGetScreenSizeRange(display: XDisplay, window: XWindow, min_width: *mut c_int, min_height: *mut c_int, max_width: *mut c_int, max_height: *mut c_int) -> c_int /* Status */;
+    pub(crate) fn XRRSelectInput(display: XDisplay, window: XWindow, mask: c_int);
+    pub(crate) fn XRRSetScreenSize(display: XDisplay, window: XWindow, width: c_int, height: c_int, mm_width: c_int, mm_height: c_int);
 }
 
 
 pub type RROutput = XID;
+pub type RRCrtc = XID;
+pub type RRMode = XID;
+/// `RR_Rotate_*` and `RR_Reflect_*` bits
+pub type Rotation = c_ushort;
+
+/// Event masks of `XRRSelectInput`
+pub(crate) const RRScreenChangeNotifyMask: c_int = 1 << 0;
+pub(crate) const RRCrtcChangeNotifyMask: c_int = 1 << 1;
+pub(crate) const RROutputChangeNotifyMask: c_int = 1 << 2;
+pub(crate) const RROutputPropertyNotifyMask: c_int = 1 << 3;
+pub(crate) const RRProviderChangeNotifyMask: c_int = 1 << 4;
+pub(crate) const RRProviderPropertyNotifyMask: c_int = 1 << 5;
+pub(crate) const RRResourceChangeNotifyMask: c_int = 1 << 6;
+
+pub(crate) const RR_Interlace: c_ulong = 0x10;
+pub(crate) const RR_DoubleScan: c_ulong = 0x20;
 
 #[derive(Debug, PartialEq)]
 #[repr(C)]
@@ -27,6 +57,71 @@ pub(crate) struct XRRMonitorInfo {
     pub(crate) outputs: *const RROutput
 }
 
+#[repr(C)]
+pub(crate) struct XRRModeInfo {
+    pub(crate) id: RRMode,
+    pub(crate) width: c_uint,
+    pub(crate) height: c_uint,
+    pub(crate) dot_clock: c_ulong,
+    pub(crate) h_sync_start: c_uint,
+    pub(crate) h_sync_end: c_uint,
+    pub(crate) h_total: c_uint,
+    pub(crate) h_skew: c_uint,
+    pub(crate) v_sync_start: c_uint,
+    pub(crate) v_sync_end: c_uint,
+    pub(crate) v_total: c_uint,
+    pub(crate) name: *const c_char,
+    pub(crate) name_length: c_uint,
+    pub(crate) mode_flags: c_ulong
+}
+
+#[repr(C)]
+pub(crate) struct XRRScreenResources {
+    pub(crate) timestamp: Time,
+    pub(crate) config_timestamp: Time,
+    pub(crate) ncrtc: c_int,
+    pub(crate) crtcs: *const RRCrtc,
+    pub(crate) noutput: c_int,
+    pub(crate) outputs: *const RROutput,
+    pub(crate) nmode: c_int,
+    pub(crate) modes: *const XRRModeInfo
+}
+
+#[repr(C)]
+pub(crate) struct XRROutputInfo {
+    pub(crate) timestamp: Time,
+    pub(crate) crtc: RRCrtc,
+    pub(crate) name: *const c_char,
+    pub(crate) name_length: c_int,
+    pub(crate) mm_width: c_ulong,
+    pub(crate) mm_height: c_ulong,
+    pub(crate) connection: c_ushort,
+    pub(crate) subpixel_order: c_ushort,
+    pub(crate) ncrtc: c_int,
+    pub(crate) crtcs: *const RRCrtc,
+    pub(crate) nclone: c_int,
+    pub(crate) clones: *const RROutput,
+    pub(crate) nmode: c_int,
+    pub(crate) npreferred: c_int,
+    pub(crate) modes: *const RRMode
+}
+
+#[repr(C)]
+pub(crate) struct XRRCrtcInfo {
+    pub(crate) timestamp: Time,
+    pub(crate) x: c_int,
+    pub(crate) y: c_int,
+    pub(crate) width: c_uint,
+    pub(crate) height: c_uint,
+    pub(crate) mode: RRMode,
+    pub(crate) rotation: Rotation,
+    pub(crate) noutput: c_int,
+    pub(crate) outputs: *const RROutput,
+    pub(crate) rotations: Rotation,
+    pub(crate) npossible: c_int,
+    pub(crate) possible: *const RROutput
+}
+
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Monitor {
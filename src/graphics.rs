@@ -1,71 +1,394 @@
+//!
+//! Simple windowing on top of the X11 display.
+//!
+//! This module allows creating top-level windows on the display and drawing
+//! onto them, e.g. to show captures taken through [`Display::capture`].
+//!
+//! # Usage
+//! ```rust,no_run
+//! # use rxscreen::Display;
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let capture = display.capture().unwrap();
+//!     if let Ok(mut window) = display.new_window()
+//!             .title("Capture")
+//!             .size((capture.width() as u32, capture.height() as u32))
+//!             .build() {
+//!         // Redraw the capture every time the window gets exposed, until the user closes the window.
+//!         window.event_loop(|window| {
+//!             window.draw(&capture);
+//!         });
+//!     }
+//! }
+//! ```
+//!
+
 use crate::Display;
-use crate::Image;
+use crate::{Image, Rgb8};
 use crate::ffi::*;
-use crate::ffi::constants::Expose;
-use crate::ffi::constants::ExposureMask;
-use crate::ffi::constants::KeyPressMask;
-use crate::ffi::events::XEvent;
+use crate::event::Event;
+use crate::ffi::constants::{False, MapNotify, Complex, CoordModeOrigin, LineSolid, CapButt, JoinMiter};
+use crate::ffi::constants::{ExposureMask, KeyPressMask, StructureNotifyMask};
+use crate::ffi::events::{XEvent, XMapEvent};
+use core::ffi::{c_char, c_int, c_ulong};
+use std::convert::TryFrom;
+use std::ffi::CString;
 
+#[derive(PartialEq, Debug)]
+pub enum GraphicsError {
+    WindowCreationFailed,
+    GcCreationFailed,
+    InvalidTitle,
+    /// A coordinate doesn't fit into the 16 bits the protocol has for the points of a polygon
+    CoordinateOutOfRange(i32, i32)
+}
 
+/// Anything that can be drawn onto a [`Window`] through [`Window::draw`].
+///
+/// Shapes are drawn with the current settings of the window, see [`Window::set_foreground`]
+/// and [`Window::set_line_width`].
 pub trait Drawable {
-    fn draw(&self, display: &Display, window: XWindow, gc: GC) -> bool;
+    fn draw(&self, window: &Window) -> bool;
+}
+
+/// A string, drawn with its baseline starting at `position`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text<'s> {
+    pub position: (i32, i32),
+    pub text: &'s str
+}
+
+impl<'s> Text<'s> {
+    pub fn new(position: (i32, i32), text: &'s str) -> Self {
+        Self { position, text }
+    }
+}
+
+/// A line from `from` to `to`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub from: (i32, i32),
+    pub to: (i32, i32)
+}
+
+impl Line {
+    pub fn new(from: (i32, i32), to: (i32, i32)) -> Self {
+        Self { from, to }
+    }
+}
+
+/// A rectangle at `position` (x, y) with `size` (width, height)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rectangle {
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub filled: bool
+}
+
+impl Rectangle {
+    pub fn outline(position: (i32, i32), size: (u32, u32)) -> Self {
+        Self { position, size, filled: false }
+    }
+    pub fn filled(position: (i32, i32), size: (u32, u32)) -> Self {
+        Self { position, size, filled: true }
+    }
+}
+
+/// An elliptical arc, inscribed into the rectangle at `position` with `size`.
+///
+/// `start` is the angle in degrees at which the arc starts, relative to the three-o'clock position,
+/// `extent` is the length of the arc in degrees. Positive angles are counter-clockwise.
+/// A filled arc is drawn as a pie slice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arc {
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub start: f32,
+    pub extent: f32,
+    pub filled: bool
+}
+
+impl Arc {
+    pub fn outline(position: (i32, i32), size: (u32, u32), start: f32, extent: f32) -> Self {
+        Self { position, size, start, extent, filled: false }
+    }
+    pub fn filled(position: (i32, i32), size: (u32, u32), start: f32, extent: f32) -> Self {
+        Self { position, size, start, extent, filled: true }
+    }
+    /// A full ellipse inscribed into the rectangle at `position` with `size`.
+    pub fn ellipse(position: (i32, i32), size: (u32, u32), filled: bool) -> Self {
+        Self { position, size, start: 0.0, extent: 360.0, filled }
+    }
+}
+
+/// A closed polygon through `points`.
+///
+/// The protocol sends points as 16-bit coordinates, so polygons with any coordinate outside of
+/// `i16::MIN..=i16::MAX` aren't drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<(i32, i32)>,
+    pub filled: bool
+}
+
+impl Polygon {
+    pub fn outline(points: impl Into<Vec<(i32, i32)>>) -> Self {
+        Self { points: points.into(), filled: false }
+    }
+    pub fn filled(points: impl Into<Vec<(i32, i32)>>) -> Self {
+        Self { points: points.into(), filled: true }
+    }
+    /// The points in the format of the protocol
+    fn xpoints(&self) -> Result<Vec<XPoint>, GraphicsError> {
+        self.points.iter().map(|&(x, y)| match (i16::try_from(x), i16::try_from(y)) {
+            (Ok(x), Ok(y)) => Ok(XPoint { x, y }),
+            _ => Err(GraphicsError::CoordinateOutOfRange(x, y))
+        }).collect()
+    }
+}
+
+impl<'s> Drawable for Text<'s> {
+    fn draw(&self, window: &Window) -> bool {
+        unsafe {
+            XDrawString(window.display.connection, window.window, window.gc, self.position.0, self.position.1,
+                self.text.as_ptr() as *const c_char, self.text.len() as i32);
+        }
+        true
+    }
+}
+
+impl Drawable for Line {
+    fn draw(&self, window: &Window) -> bool {
+        unsafe {
+            XDrawLine(window.display.connection, window.window, window.gc, self.from.0, self.from.1, self.to.0, self.to.1);
+        }
+        true
+    }
+}
+
+impl Drawable for Rectangle {
+    fn draw(&self, window: &Window) -> bool {
+        unsafe {
+            if self.filled {
+                XFillRectangle(window.display.connection, window.window, window.gc, self.position.0, self.position.1, self.size.0, self.size.1);
+            } else {
+                XDrawRectangle(window.display.connection, window.window, window.gc, self.position.0, self.position.1, self.size.0, self.size.1);
+            }
+        }
+        true
+    }
 }
 
+impl Drawable for Arc {
+    fn draw(&self, window: &Window) -> bool {
+        // X11 measures angles in 64ths of a degree
+        let (start, extent) = ((self.start * 64.0) as i32, (self.extent * 64.0) as i32);
+        unsafe {
+            if self.filled {
+                XFillArc(window.display.connection, window.window, window.gc, self.position.0, self.position.1, self.size.0, self.size.1, start, extent);
+            } else {
+                XDrawArc(window.display.connection, window.window, window.gc, self.position.0, self.position.1, self.size.0, self.size.1, start, extent);
+            }
+        }
+        true
+    }
+}
 
-impl<'a, T: AsRef<str>> Drawable for T
-    where T: Copy, Vec<u8>: From<T> {
-    fn draw(&self, display: &Display, window: XWindow, gc: GC) -> bool {
-        use std::ffi::CString;
+impl Drawable for Polygon {
+    fn draw(&self, window: &Window) -> bool {
+        if self.points.len() < 2 {
+            return false;
+        }
+        let Ok(mut points) = self.xpoints() else { return false };
         unsafe {
-            let cstr = CString::new(*self).unwrap();
-            XDrawString(display.connection, window, gc, 30, 30, cstr.clone().into_raw(), cstr.as_bytes_with_nul().len() as i32);
+            if self.filled {
+                XFillPolygon(window.display.connection, window.window, window.gc, points.as_ptr(), points.len() as i32, Complex as i32, CoordModeOrigin as i32);
+            } else {
+                // Close the outline by returning to the first point
+                points.push(points[0]);
+                XDrawLines(window.display.connection, window.window, window.gc, points.as_ptr(), points.len() as i32, CoordModeOrigin as i32);
+            }
         }
         true
     }
 }
 
-impl Drawable for &Image {
-    fn draw(&self, display: &Display, window: XWindow, gc: GC) -> bool {
+impl<T: Drawable + ?Sized> Drawable for &T {
+    fn draw(&self, window: &Window) -> bool {
+        (**self).draw(window)
+    }
+}
+
+impl Drawable for Image {
+    fn draw(&self, window: &Window) -> bool {
         unsafe {
-            XPutImage(display.connection, window, gc, self.raw, 0, 0, 0, 0, (*self.raw).width as u32, (*self.raw).height as u32);
+            if self.detached {
+                // Xlib converts images not matching the window through their functions, which detached images lack
+                XInitImage(self.raw as *mut XImage);
+            }
+            XPutImage(window.display.connection, window.window, window.gc, self.raw, 0, 0, 0, 0, (*self.raw).width as u32, (*self.raw).height as u32);
         }
         true
     }
 }
 
+/// A top-level window created through [`WindowBuilder::build`].
+///
+/// The window is destroyed once this struct is dropped.
 pub struct Window<'a> {
     display: &'a Display,
     window: XWindow,
-    gc: GC,
-    visual: *const Visual
+    pub(crate) gc: GC,
+    size: (u32, u32),
+    wm_delete_window: Atom,
+    closed: bool,
+    destroyed: bool
 }
 
 impl<'a> Window<'a> {
-    pub fn event_loop(&self) {
-        unsafe {
-            let mut xev = XEvent { r#type: 0 };
-            loop {
-                XNextEvent(self.display.connection, &mut xev);
-                println!("Got Event {}", xev.r#type);
+    /// Process events of this window until the user closes it.
+    ///
+    /// `on_expose` is called every time (parts of) the window have to be redrawn, i.e.
+    /// after it got mapped, uncovered or resized.
+    /// The loop returns once the window manager requests the window to be closed
+    /// (`WM_DELETE_WINDOW`), or the window got destroyed.
+    pub fn event_loop(&mut self, mut on_expose: impl FnMut(&Window)) {
+        while !self.closed {
+            let event = self.display.next_event();
+            self.handle_event(&event);
+            if let Event::Expose { window, count: 0, .. } = event {
+                if window == self.window {
+                    on_expose(self);
+                    unsafe { XFlush(self.display.connection) };
+                }
             }
         }
     }
+
+    /// Update the state of the window (size, closed) from an event read from the display.
+    ///
+    /// Use this when reading events through [`Display::next_event`] instead of [`Window::event_loop`].
+    /// Events of other windows are ignored.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::Configure { window, width, height, .. } if window == self.window => {
+                self.size = (width as u32, height as u32);
+            },
+            Event::ClientMessage { window, data, .. } if window == self.window && data[0] as Atom == self.wm_delete_window => {
+                self.closed = true;
+            },
+            Event::Destroy { window } if window == self.window => {
+                self.closed = true;
+                self.destroyed = true;
+            },
+            _ => {}
+        }
+    }
+    /// Draw `drawable` onto the window.
+    /// ```rust,no_run
+    /// # use rxscreen::{Display, Rgb8, graphics::{Line, Rectangle, Text}};
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     if let Ok(mut window) = display.new_window().build() {
+    ///         window.event_loop(|window| {
+    ///             window.set_foreground(Rgb8 { r: 255, g: 0, b: 0 });
+    ///             window.set_line_width(2);
+    ///             window.draw(Rectangle::outline((10, 10), (200, 100)));
+    ///             window.draw(Line::new((10, 10), (210, 110)));
+    ///             window.draw(Text::new((20, 130), "Selection"));
+    ///         });
+    ///     }
+    /// }
+    /// ```
     pub fn draw(&self, drawable: impl Drawable) -> bool {
-        drawable.draw(self.display, self.window, self.gc);
-        true
+        drawable.draw(self)
+    }
+
+    /// Set the color used to draw shapes and text.
+    ///
+    /// The color is converted assuming a 24-bit TrueColor visual, as used by virtually every X server.
+    pub fn set_foreground(&self, color: Rgb8) {
+        unsafe { XSetForeground(self.display.connection, self.gc, pixel(color)) };
+    }
+    /// Set the background color, used e.g. for dashed lines.
+    pub fn set_background(&self, color: Rgb8) {
+        unsafe { XSetBackground(self.display.connection, self.gc, pixel(color)) };
+    }
+    /// Set the width of lines and outlines in pixels, `0` draws the thinnest possible lines.
+    pub fn set_line_width(&self, width: u32) {
+        unsafe { XSetLineAttributes(self.display.connection, self.gc, width, LineSolid as i32, CapButt as i32, JoinMiter as i32) };
+    }
+
+    /// Set the title of the window
+    pub fn set_title(&self, title: &str) -> Result<(), GraphicsError> {
+        let title = CString::new(title).map_err(|_| GraphicsError::InvalidTitle)?;
+        unsafe {
+            XStoreName(self.display.connection, self.window, title.as_ptr());
+            XFlush(self.display.connection);
+        }
+        Ok(())
+    }
+    /// Resize the window to `size` (width, height).
+    pub fn resize(&mut self, size: (u32, u32)) {
+        unsafe {
+            XResizeWindow(self.display.connection, self.window, size.0, size.1);
+            XFlush(self.display.connection);
+        }
+        self.size = size;
+    }
+    /// Move the window to `offset` (x, y), relative to the root window.
+    pub fn move_to(&self, offset: (i32, i32)) {
+        unsafe {
+            XMoveWindow(self.display.connection, self.window, offset.0, offset.1);
+            XFlush(self.display.connection);
+        }
+    }
+    /// The X11 id of the window, as reported by [`Event::window`].
+    pub fn id(&self) -> XWindow {
+        self.window
     }
+    /// The current size (width, height) of the window.
+    ///
+    /// This is updated whenever the window gets resized, either through [`Window::resize`] or
+    /// the window manager, in which case the new size is known after the next [`Window::event_loop`] iteration.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+    /// Whether the window has been closed by the user or destroyed.
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+}
 
+fn pixel(color: Rgb8) -> c_ulong {
+    ((color.r as c_ulong) << 16) | ((color.g as c_ulong) << 8) | color.b as c_ulong
+}
+
+impl<'a> Drop for Window<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            XFreeGC(self.display.connection, self.gc);
+            if !self.destroyed {
+                XDestroyWindow(self.display.connection, self.window);
+            }
+            XFlush(self.display.connection);
+        }
+    }
 }
 
 
 pub struct WindowBuilder<'a> {
     display: &'a Display,
+    title: Option<String>,
     offset: (i32, i32),
     size: (u32, u32),
     border_width: u32
 }
 
 impl<'a> WindowBuilder<'a> {
+    /// Set the title of the window
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
     pub fn size(mut self, size: (u32, u32)) -> Self {
         self.size = size;
         self
@@ -78,44 +401,84 @@ impl<'a> WindowBuilder<'a> {
         self.border_width = border_width;
         self
     }
-    pub fn build(self) -> Result<Window<'a>, ()> {
+    /// Create and map the window with the configured parameters.
+    ///
+    /// This function blocks until the window is mapped. Its initial `Expose` event is left queued, so
+    /// [`Window::event_loop`] draws the window right away.
+    /// ```rust
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let window = display.new_window()
+    ///             .title("rxscreen")
+    ///             .offset((100, 100))
+    ///             .size((800, 600))
+    ///             .build();
+    /// }
+    /// ```
+    pub fn build(self) -> Result<Window<'a>, GraphicsError> {
+        let title = match self.title {
+            Some(title) => Some(CString::new(title).map_err(|_| GraphicsError::InvalidTitle)?),
+            None => None
+        };
         unsafe {
-            let _wd = XBlackPixel(self.display.connection, 0);
-            let _ww = XWhitePixel(self.display.connection, 0);
-            let window = XCreateSimpleWindow(self.display.connection, self.display.window, 0, 0, 600, 400, 0, 0, 0);
+            let black = XBlackPixel(self.display.connection, 0);
+            let window = XCreateSimpleWindow(self.display.connection, self.display.window, self.offset.0, self.offset.1,
+                self.size.0, self.size.1, self.border_width, black, black);
+            if window == 0 {
+                return Err(GraphicsError::WindowCreationFailed);
+            }
 
-            let visual = XDefaultVisual(self.display.connection, 0);
-            XSelectInput(self.display.connection, window, (ExposureMask | KeyPressMask) as u64);
-            XMapWindow(self.display.connection, window);
-            XFlush(self.display.connection);
+            if let Some(title) = title {
+                XStoreName(self.display.connection, window, title.as_ptr());
+            }
+
+            // Ask the window manager to notify us instead of killing the connection once the
+            // user closes the window.
+            let wm_delete_window = XInternAtom(self.display.connection, b"WM_DELETE_WINDOW\0".as_ptr() as *const c_char, False as i32);
+            XSetWMProtocols(self.display.connection, window, &wm_delete_window, 1);
+
+            XSelectInput(self.display.connection, window, (ExposureMask | KeyPressMask | StructureNotifyMask) as u64);
 
             let gc = XCreateGC(self.display.connection, window, 0, std::ptr::null());
+            if gc.is_null() {
+                XDestroyWindow(self.display.connection, window);
+                return Err(GraphicsError::GcCreationFailed);
+            }
 
+            XMapWindow(self.display.connection, window);
+            XFlush(self.display.connection);
 
-            // Wait until X-Server sends the Expose event
+            // Wait until the window is mapped. Its initial Expose stays queued for `event_loop`,
+            // just like events of other windows.
             let mut xev = XEvent { r#type: 0 };
-            loop {
-                XNextEvent(self.display.connection, &mut xev);
-                if xev.r#type == Expose as i32 {
-                    break;
-                }
-            }
+            XIfEvent(self.display.connection, &mut xev, is_mapped, &window as *const XWindow as XPointer);
 
             Ok(Window {
                 display: self.display,
                 gc,
                 window,
-                visual
+                size: self.size,
+                wm_delete_window,
+                closed: false,
+                destroyed: false
             })
         }
     }
 }
 
 
+/// Matches the `MapNotify` event of the window `arg` points to
+unsafe extern "C" fn is_mapped(_display: XDisplay, event: *mut XEvent, arg: XPointer) -> c_int {
+    let event = &*(event as *const XMapEvent);
+    (event.type_ == MapNotify as i32 && event.window == *(arg as *const XWindow)) as c_int
+}
+
 impl Display {
-    fn new_window<'a>(&'a self) -> WindowBuilder<'a> {
+    /// Create a new top-level window on this display, see [`WindowBuilder`] for the configuration.
+    pub fn new_window(&self) -> WindowBuilder<'_> {
         WindowBuilder {
-            display: &self,
+            display: self,
+            title: None,
             offset: (0,0),
             size: (600, 400),
             border_width: 0
@@ -127,9 +490,22 @@ impl Display {
 
 #[test]
 fn can_create_blank_window() {
-    let display = Display::new(":0.0").unwrap();
-    display.new_window()
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    assert!(display.new_window()
             .size((600, 400))
-            .build();
+            .build()
+            .is_ok());
 }
 
+#[test]
+fn converts_colors_to_truecolor_pixels() {
+    assert_eq!(pixel(Rgb8 { r: 0x12, g: 0x34, b: 0x56 }), 0x123456);
+}
+
+#[test]
+fn refuses_polygons_beyond_16_bit_coordinates() {
+    let polygon = Polygon::filled([(0, 0), (i16::MAX as i32, 10), (-20, i16::MIN as i32)]);
+    assert_eq!(polygon.xpoints().unwrap()[2], XPoint { x: -20, y: i16::MIN });
+    let polygon = Polygon::outline([(0, 0), (40000, 10), (20, 20)]);
+    assert_eq!(polygon.xpoints(), Err(GraphicsError::CoordinateOutOfRange(40000, 10)));
+}
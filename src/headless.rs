@@ -0,0 +1,278 @@
+//!
+//! Disposable X servers for tests and build machines without a desktop.
+//!
+//! A [`VirtualDisplay`] starts `Xvfb` (or `Xephyr`) on a free display number, waits until the server
+//! accepts connections, and connects to it. The server is killed once the virtual display is dropped.
+//! It dereferences to the connected [`Display`], so it can be used wherever a display is expected.
+//!
+//! The server picks the display number itself and reports it through `-displayfd`, so virtual
+//! displays can be spawned by tests running in parallel.
+//!
+//! # Usage
+//! ```rust,no_run
+//! # use rxscreen::headless::VirtualDisplay;
+//! let display = VirtualDisplay::spawn(1280, 720, 24).unwrap();
+//! println!("Running on {}", display.name());
+//! let capture = display.capture().unwrap();
+//! assert_eq!(capture.width(), 1280);
+//! ```
+//!
+
+use std::io::{self, Read};
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use crate::{Display, DisplayCreationError};
+
+#[derive(Debug)]
+pub enum VirtualDisplayError {
+    /// The server couldn't be started, e.g. as it isn't installed
+    Spawn(io::Error),
+    /// The server exited before it was ready
+    Exited,
+    /// The server wasn't ready within the timeout
+    Timeout,
+    /// The server is running, but couldn't be connected to
+    Connect(DisplayCreationError),
+    /// The server rejected the monitor of this name, e.g. as the name is empty
+    Monitor(String)
+}
+
+impl From<io::Error> for VirtualDisplayError {
+    fn from(error: io::Error) -> Self {
+        VirtualDisplayError::Spawn(error)
+    }
+}
+
+/// The X servers a [`VirtualDisplay`] can run on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Server {
+    /// `Xvfb`, rendering into memory only
+    Xvfb,
+    /// `Xephyr`, rendering into a window of the current display, e.g. to watch a test
+    Xephyr
+}
+
+impl Server {
+    fn command(&self) -> &'static str {
+        match self {
+            Server::Xvfb => "Xvfb",
+            Server::Xephyr => "Xephyr"
+        }
+    }
+    fn screen(&self, size: (u32, u32), depth: u32) -> Vec<String> {
+        let screen = format!("{}x{}x{}", size.0, size.1, depth);
+        match self {
+            Server::Xvfb => vec!["-screen".into(), "0".into(), screen],
+            Server::Xephyr => vec!["-screen".into(), screen]
+        }
+    }
+}
+
+/// A monitor to set up on a virtual display through RandR
+#[cfg(feature = "xrandr")]
+struct MonitorLayout {
+    name: String,
+    offset: (i32, i32),
+    size: (i32, i32),
+    primary: bool
+}
+
+/// An X server started for the lifetime of this struct, see the [module documentation](self).
+pub struct VirtualDisplay {
+    display: ManuallyDrop<Display>,
+    server: Child,
+    number: u32
+}
+
+impl VirtualDisplay {
+    /// Start `Xvfb` with a screen of `width` x `height` pixels and `depth` bits per pixel, and connect to it
+    pub fn spawn(width: u32, height: u32, depth: u32) -> Result<Self, VirtualDisplayError> {
+        Self::builder(width, height).depth(depth).spawn()
+    }
+    /// Configure a virtual display with a screen of `width` x `height` pixels
+    pub fn builder(width: u32, height: u32) -> VirtualDisplayBuilder {
+        VirtualDisplayBuilder {
+            server: Server::Xvfb,
+            size: (width, height),
+            depth: 24,
+            timeout: Duration::from_secs(10),
+            #[cfg(feature = "xrandr")]
+            monitors: Vec::new()
+        }
+    }
+    /// The number of the display, as in `:<number>`
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+    /// The name of the display, e.g. to set `DISPLAY` for child processes
+    pub fn name(&self) -> String {
+        format!(":{}", self.number)
+    }
+}
+
+impl Deref for VirtualDisplay {
+    type Target = Display;
+    fn deref(&self) -> &Display {
+        &self.display
+    }
+}
+
+impl Drop for VirtualDisplay {
+    fn drop(&mut self) {
+        // Closing the connection to a server that is gone already fails fatally, so it goes first
+        unsafe { ManuallyDrop::drop(&mut self.display) };
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+pub struct VirtualDisplayBuilder {
+    server: Server,
+    size: (u32, u32),
+    depth: u32,
+    timeout: Duration,
+    #[cfg(feature = "xrandr")]
+    monitors: Vec<MonitorLayout>
+}
+
+impl VirtualDisplayBuilder {
+    /// Set the bits per pixel of the screen, 24 by default
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+    /// Set the server to run, `Xvfb` by default
+    pub fn server(mut self, server: Server) -> Self {
+        self.server = server;
+        self
+    }
+    /// Set how long to wait for the server to be ready, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Add a monitor named `name`, covering `size` (width, height) at `offset` (x, y) of the screen.
+    /// Without monitors, the server reports a single one covering the whole screen.
+    #[cfg(feature = "xrandr")]
+    pub fn monitor(mut self, name: impl Into<String>, offset: (i32, i32), size: (i32, i32), primary: bool) -> Self {
+        self.monitors.push(MonitorLayout { name: name.into(), offset, size, primary });
+        self
+    }
+
+    /// Start the server, wait until it is ready, and connect to it.
+    pub fn spawn(self) -> Result<VirtualDisplay, VirtualDisplayError> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // Owned, so both ends are closed no matter how this function returns
+        let (mut ready, writer) = unsafe { (std::fs::File::from_raw_fd(fds[0]), std::fs::File::from_raw_fd(fds[1])) };
+
+        let mut command = Command::new(self.server.command());
+        command.arg("-displayfd").arg(fds[1].to_string())
+            .args(self.server.screen(self.size, self.depth))
+            .args(["-nolisten", "tcp", "-noreset"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let write_end = fds[1];
+        unsafe {
+            // Only the server inherits the write end, not processes spawned concurrently
+            command.pre_exec(move || match libc::fcntl(write_end, libc::F_SETFD, 0) {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(())
+            });
+        }
+        let mut server = command.spawn()?;
+        drop(writer);
+
+        // The server writes its display number, followed by a newline, once it accepts connections
+        let number = match read_display_number(&mut ready, self.timeout) {
+            Ok(number) => number,
+            Err(error) => {
+                let _ = server.kill();
+                let _ = server.wait();
+                return Err(error);
+            }
+        };
+        let display = match Display::new(format!(":{}", number)) {
+            Ok(display) => display,
+            Err(error) => {
+                let _ = server.kill();
+                let _ = server.wait();
+                return Err(VirtualDisplayError::Connect(error));
+            }
+        };
+        // Dropped on failure, which kills the server
+        let display = VirtualDisplay { display: ManuallyDrop::new(display), server, number };
+        #[cfg(feature = "xrandr")]
+        for monitor in &self.monitors {
+            if !display.set_monitor(&monitor.name, monitor.offset, monitor.size, monitor.primary) {
+                return Err(VirtualDisplayError::Monitor(monitor.name.clone()));
+            }
+        }
+        Ok(display)
+    }
+}
+
+/// Read the display number from `-displayfd`, waiting at most `timeout`
+fn read_display_number(ready: &mut std::fs::File, timeout: Duration) -> Result<u32, VirtualDisplayError> {
+    use std::os::unix::io::AsRawFd;
+    let deadline = std::time::Instant::now() + timeout;
+    let mut output = Vec::new();
+    while !output.ends_with(b"\n") {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        let mut poll = libc::pollfd { fd: ready.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut poll, 1, remaining.as_millis().min(i32::MAX as u128) as i32) } {
+            -1 => return Err(io::Error::last_os_error().into()),
+            0 => return Err(VirtualDisplayError::Timeout),
+            _ => {}
+        }
+        let mut buffer = [0; 16];
+        match ready.read(&mut buffer)? {
+            0 => return Err(VirtualDisplayError::Exited),
+            read => output.extend_from_slice(&buffer[..read])
+        }
+    }
+    std::str::from_utf8(&output).ok()
+        .and_then(|number| number.trim().parse().ok())
+        .ok_or(VirtualDisplayError::Exited)
+}
+
+/// Spawn a virtual display for a test, or `None` to skip the test if `Xvfb` isn't installed
+#[cfg(test)]
+pub(crate) fn test_display(width: u32, height: u32) -> Option<VirtualDisplay> {
+    match VirtualDisplay::spawn(width, height, 24) {
+        Ok(display) => Some(display),
+        Err(VirtualDisplayError::Spawn(error)) if error.kind() == io::ErrorKind::NotFound => {
+            eprintln!("Skipping test, Xvfb isn't installed");
+            None
+        },
+        Err(error) => panic!("Couldn't spawn a virtual display: {:?}", error)
+    }
+}
+
+#[test]
+fn can_spawn_virtual_displays() {
+    let Some(display) = test_display(320, 240) else { return };
+    let capture = display.capture().unwrap();
+    assert_eq!((capture.width(), capture.height()), (320, 240));
+}
+
+#[cfg(feature = "xrandr")]
+#[test]
+fn refuses_invalid_monitor_layouts() {
+    let spawned = VirtualDisplay::builder(320, 240)
+        .monitor("left", (0, 0), (160, 240), true)
+        .monitor("", (160, 0), (160, 240), false)
+        .spawn();
+    if matches!(&spawned, Err(VirtualDisplayError::Spawn(error)) if error.kind() == io::ErrorKind::NotFound) {
+        eprintln!("Skipping test, Xvfb isn't installed");
+        return;
+    }
+    assert!(matches!(spawned, Err(VirtualDisplayError::Monitor(name)) if name.is_empty()));
+}
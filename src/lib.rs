@@ -4,6 +4,10 @@
 //! - X11
 //! - Xrandr (via the `xrandr` feature)
 //! - MIT-SHM (via the `shm` feature)
+//! - Windows to draw on, and live previews of captures (via the `graphics` feature)
+//!
+//! With the `dlopen` feature, the X libraries are loaded at runtime instead of being linked, so binaries
+//! start on machines without them, e.g. Wayland-only or headless servers, and [`Display::new`] fails instead.
 //!
 //! # Examples
 //!
@@ -46,21 +50,47 @@
 
 use core::ffi::c_char;
 use std::ffi::CString;
+use std::sync::OnceLock;
 
 pub mod ffi;
 pub use ffi::{Rgb8, Bgr8};
-//mod graphics;
+pub mod event;
+pub mod codec;
+pub mod clock;
+pub mod record;
+pub mod yuv;
+pub mod session;
+pub mod capture;
+pub mod mock;
+pub mod headless;
+
+#[cfg(feature = "graphics")]
+pub mod graphics;
+#[cfg(feature = "graphics")]
+pub mod preview;
 
 #[cfg(feature = "xrandr")]
 pub mod monitor;
 
+#[cfg(feature = "damage")]
+pub mod damage;
+
 #[cfg(feature = "shm")]
 pub mod shm;
 
+#[cfg(any(feature = "shm", feature = "xrandr"))]
+mod trap;
+
 #[cfg(feature = "mouse")]
 pub mod mouse;
 
-use ffi::{*, constants::{AllPlanes, ZPixmap}};
+#[cfg(feature = "save")]
+pub mod save;
+
+#[cfg(feature = "wire")]
+pub mod wire;
+
+use ffi::{*, constants::{AllPlanes, LSBFirst, ZPixmap}};
 
 
 pub struct Display {
@@ -68,10 +98,13 @@ pub struct Display {
 	window: ffi::XWindow,
 	pub width: u32,
 	pub height: u32,
+	extensions: OnceLock<event::ExtensionEvents>,
 }
 
 pub struct Image {
-	raw: *const XImage
+	raw: *const XImage,
+	/// Whether the image and its data were allocated in Rust by [`Image::detached`], rather than by Xlib
+	detached: bool
 }
 
 #[derive(Debug)]
@@ -95,7 +128,15 @@ impl Display {
 	/// 
 	/// If the call to `XOpenDisplay` fails, or if `display_identifier` couldn't be converted to a C String, then this function will
 	/// return a DisplayCreationError with details 
+	/// With the `dlopen` feature, it also fails if `libX11.so.6` couldn't be loaded.
 	pub fn new(display_identifier: impl Into<String>) -> Result<Self, DisplayCreationError> {
+		#[cfg(feature = "dlopen")]
+		if !ffi::library::X11.available() {
+			return Err(DisplayCreationError { description: format!("X11 libraries not available: couldn't load {}", ffi::library::X11.name()) });
+		}
+		if !init_threads() {
+			return Err(DisplayCreationError { description: "Couldn't initialize Xlib for threads: XInitThreads failed".into() });
+		}
 		match CString::new(display_identifier.into()) {
 			Ok(location) => {
 				let display = unsafe { XOpenDisplay(location.as_ptr()) };
@@ -111,7 +152,8 @@ impl Display {
 						connection: display,
 						window: default_window,
 						width,
-						height
+						height,
+						extensions: OnceLock::new()
 					})
 				}else{
 					Err(DisplayCreationError { description: "Couldn't open display: XOpenDisplay returned NULL".into() })
@@ -140,11 +182,13 @@ impl Display {
 	/// # Errors
 	/// 
 	/// This function fails silently if the call to `XGetImage` fails for some reason.
+	#[allow(clippy::result_unit_err)]
 	pub fn capture(&self) -> Result<Image, ()> {
 		let image = unsafe { XGetImage(self.connection, self.window, 0, 0, self.width, self.height, AllPlanes, ZPixmap as i32) };
 		if !image.is_null() {
 			Ok(Image {
-			    raw: image
+			    raw: image,
+			    detached: false
 			})
 		}else{
 			Err(())
@@ -166,26 +210,41 @@ impl Display {
 	/// # Errors
 	/// 
 	/// This function fails silently if the call to `XGetImage` fails for some reason.
+	#[allow(clippy::result_unit_err)]
 	pub fn capture_area(&self, offset: (u32, u32), size: (u32, u32)) -> Result<Image, ()> {
 		let image = unsafe { XGetImage(self.connection, self.window, offset.0 as i32, offset.1 as i32, size.0, size.1, AllPlanes, ZPixmap as i32) };
 		if !image.is_null() {
 			Ok(Image {
-			    raw: image
+			    raw: image,
+			    detached: false
 			})
 		}else{
 			Err(())
 		}
 	}
 
+	/// Whether the area of `size` at `offset` lies within the screen.
+	/// Capture requests reaching past it fail with `BadMatch`, which the default error handler of Xlib exits on.
+	pub(crate) fn contains(&self, offset: (u32, u32), size: (u32, u32)) -> bool {
+		offset.0.checked_add(size.0).is_some_and(|right| right <= self.width)
+			&& offset.1.checked_add(size.1).is_some_and(|bottom| bottom <= self.height)
+	}
 }
 
 impl Image {
+    /// Wrap an existing buffer of 32-bit pixels into an `XImage`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to at least `width * height * 4` bytes that were allocated with `malloc`,
+    /// as ownership of the buffer is passed to the image and released through `XDestroyImage`.
     pub unsafe fn from_raw_parts(display: &Display, data: *const u8, width: u32, height: u32) -> Self {
         let visual = XDefaultVisual(display.connection, 0);
         let ximg = XCreateImage(display.connection, visual, 24, ZPixmap as i32, 0, data as *const c_char, width, height, 32, 0);
         // TODO: check ximg for null-ptr
         Self {
-            raw: ximg
+            raw: ximg,
+            detached: false
         }
     }
 
@@ -213,18 +272,31 @@ impl Image {
 	///                                 .collect();
 	/// }
 	/// ```
+	///
+	/// # Safety
+	///
+	/// The returned slice is not tied to the lifetime of the image, it must not outlive it.
 	pub unsafe fn as_raw_slice<'a>(&self) -> &'a [Bgr8] {
 		let blob_length = ((*self.raw).width * (*self.raw).height) as usize;
 		std::slice::from_raw_parts((*self.raw).data as *const Bgr8, blob_length)
 	}
 
     /// Returns a slice of the raw image data
-    pub unsafe fn as_bytes<'a>(&'a self) -> &'a [u8] {
-        let length = self.width() * self.height() * ((*self.raw).depth / 8);
+    ///
+    /// # Safety
+    ///
+    /// The image must not be written to by the X server (e.g. through a shared session) while the slice is alive.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        let length = (*self.raw).bytes_per_line * self.height();
         std::slice::from_raw_parts((*self.raw).data as *const u8, length as usize)
     }
-    pub unsafe fn as_bytes_mut<'a>(&'a mut self) -> &'a mut [u8] {
-        let length = self.width() * self.height() * ((*self.raw).depth / 8);
+    /// Returns a mutable slice of the raw image data
+    ///
+    /// # Safety
+    ///
+    /// The image must not be written to by the X server (e.g. through a shared session) while the slice is alive.
+    pub unsafe fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let length = (*self.raw).bytes_per_line * self.height();
         let slice = std::slice::from_raw_parts_mut((*self.raw).data as *mut u8, length as usize);
         slice
     }
@@ -238,95 +310,97 @@ impl Image {
 
             let ximg = XCreateImage(display.connection, visual, 24, ZPixmap as i32, 0, data as *const c_char, width, height, 32, 0);
             Self {
-                raw: ximg
+                raw: ximg,
+                detached: false
             }
         }
     }
 
+    /// Create a new black image in BGRX without a display.
+    /// The image and its data are allocated in Rust and released without Xlib, so it doesn't need libX11.
+    pub(crate) fn detached(width: u32, height: u32) -> Self {
+        let data = vec![0u8; (width as usize * height as usize * 4).max(1)].into_boxed_slice();
+        let ximg = Box::new(XImage {
+            width: width as i32,
+            height: height as i32,
+            xoffset: 0,
+            format: ZPixmap as i32,
+            data: Box::into_raw(data) as *mut c_char,
+            byte_order: LSBFirst as i32,
+            bitmap_unit: 32,
+            bitmap_bit_order: LSBFirst as i32,
+            bitmap_pad: 32,
+            depth: 24,
+            bytes_per_line: width as i32 * 4,
+            bits_per_pixel: 32,
+            red_mask: 0xff0000,
+            green_mask: 0xff00,
+            blue_mask: 0xff,
+            obdata: std::ptr::null_mut(),
+            // Filled in through `XInitImage` before the image is handed to Xlib
+            f: unsafe { std::mem::zeroed() }
+        });
+        Self {
+            raw: Box::into_raw(ximg),
+            detached: true
+        }
+    }
+
     /// Returns the pointer for the internal data buffer.
+    ///
+    /// # Safety
+    ///
+    /// The pointer is only valid as long as the image is alive.
     pub unsafe fn as_ptr(&self) -> *const u8 {
         (*self.raw).data as *const u8
     }
 
-	#[cfg(feature = "save")]
-	/// Saves the image to file
-	///
-	///	You can save as any filetype that the `image`-crate supports
-	///
-	/// ```rust
-	/// # use rxscreen::Display;
-	/// if let Ok(display) = Display::new(":0.0") {
-	///		let screenshot = display.capture();
-	///		screenshot.unwrap().save_as("./screenshot.png");
-	/// }
-	/// ```
-	///
-	/// # Warning
-	///
-	/// **Without** configuring any opt-level (debug build) this function may take upwards of 10 seconds for one screenshot
-	/// (measured on debug build, 5760x1080 capture, ryzen 7 2700x, gtx 1050 ti)
-	///
-	/// **With opt-level 3** it takes 0.2 seconds for a capture of the same size.
-	/// 
-
-	pub fn save_as(&self, file: impl Into<std::path::PathBuf>) -> std::io::Result<()> {
-		use image::{save_buffer, ColorType};
-		// Restructure buffer to fit RGB instead of BGRP
-		let (width, height) = unsafe { ((*self.raw).width, (*self.raw).height) };
-
-		fn thrice<T>(first: T, second: T, third: T) -> impl Iterator<Item = T> {
-			use std::iter::once;
-			once(first).chain(once(second)).chain(once(third))
-		}
+    /// Create an image from tightly packed pixels of `channels` bytes each (RGB or RGBA).
+    /// The alpha channel is dropped, as X11 visuals carry none.
+    pub(crate) fn from_packed(display: &Display, width: u32, height: u32, channels: usize, data: &[u8]) -> Self {
+        let mut image = Image::empty(display, width, height);
+        let (width, stride) = (width as usize, unsafe { (*image.raw).bytes_per_line as usize });
+        let target = unsafe { image.as_bytes_mut() };
+        for (row, source) in target.chunks_exact_mut(stride).zip(data.chunks_exact(width * channels)) {
+            for (bgrx, pixel) in row.chunks_exact_mut(4).zip(source.chunks_exact(channels)) {
+                bgrx.copy_from_slice(&[pixel[2], pixel[1], pixel[0], 0]);
+            }
+        }
+        image
+    }
+}
 
-		let buffer = unsafe { self.as_raw_slice() };
-		let buffer = buffer.into_iter().map(|brg| thrice(brg.r, brg.g, brg.b)).flatten().collect::<Vec<u8>>();
+// An image owns its pixel buffer and doesn't use the connection to the display,
+// so it can be moved to, and read from other threads.
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
 
-		match save_buffer(file.into(), &buffer, width as u32, height as u32, ColorType::Rgb8) {
-			Ok(()) => Ok(()),
-			Err(_) => Err(std::io::Error::new(std::io::ErrorKind::Other, "Couldn't write to file"))
+impl Drop for Image {
+	fn drop(&mut self) {
+		if !self.detached {
+			unsafe{ crate::XDestroyImage(self.raw); }
+			return;
 		}
-	}
-
-	#[cfg(feature = "save")]
-	/// Saves the raw capture to PNG in memory
-	///
-	/// ```rust
-	///	# use rxscreen::{Display, Image};
-	/// if let Ok(display) = Display::new(":0.0") {
-	///		let screenshot = display.capture().unwrap();
-	///		let data = screenshot.save_to_memory();
-	///		// `data` now contains the encoded PNG file
-	/// }
-	/// ```
-	pub fn save_to_memory(self) -> std::io::Result<Vec<u8>>{
-		use image::{codecs::png::PngEncoder, ColorType};
-		
-		fn thrice<T>(first: T, second: T, third: T) -> impl Iterator<Item = T> {
-			use std::iter::once;
-			once(first).chain(once(second)).chain(once(third))
+		unsafe {
+			let ximg = Box::from_raw(self.raw as *mut XImage);
+			let length = (ximg.bytes_per_line as usize * ximg.height as usize).max(1);
+			drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ximg.data as *mut u8, length)));
 		}
-
-		// Restructure BGR8 into RGB8
-		let buffer = unsafe { self.as_raw_slice() };
-		let buffer = buffer.into_iter().map(|brg| thrice(brg.r, brg.g, brg.b)).flatten().collect::<Vec<u8>>();
-
-		let mut png_data: Vec<u8> = vec![];
-
-		let encoder = PngEncoder::new(&mut png_data);
-		let (width, height) = unsafe { ((*self.raw).width, (*self.raw).height) };
-		encoder.encode(&buffer, width as u32, height as u32, ColorType::Rgb8);
-		
-		Ok(png_data)
 	}
 }
 
-impl Drop for Image {
-	fn drop(&mut self) {
-		unsafe{ crate::XDestroyImage(self.raw); }
-	}
+/// Make Xlib lock every connection, so displays can be used from multiple threads.
+/// `XInitThreads` has to precede any other Xlib call, so it is called once, before the first display is opened.
+fn init_threads() -> bool {
+	static INITIALIZED: OnceLock<bool> = OnceLock::new();
+	*INITIALIZED.get_or_init(|| unsafe { XInitThreads() } != 0)
 }
 
+// Xlib serializes the requests on a connection once `XInitThreads` was called, which `Display::new`
+// makes sure of, so displays can be moved to and shared between threads.
+unsafe impl Send for Display {}
+unsafe impl Sync for Display {}
+
 impl Drop for Display {
 	fn drop(&mut self) {
 		unsafe { XCloseDisplay(self.connection) };
@@ -338,11 +412,12 @@ impl Drop for Display {
 mod tests {
 	#[test]
 	pub fn can_open_x11_display() {
-		assert_eq!(crate::Display::new(":0.0").is_ok(), true);
+		let Some(display) = crate::headless::test_display(320, 240) else { return };
+		assert!(crate::Display::new(display.name()).is_ok());
 	}
 	#[test]
 	pub fn fails_on_incorrect_display() {
-		assert_eq!(crate::Display::new("test:5.0").is_err(), true);
+		assert!(crate::Display::new("test:5.0").is_err());
 	}
 }
 
@@ -0,0 +1,248 @@
+//!
+//! A display backed by in-memory frames, for tests without an X server.
+//!
+//! A [`MockDisplay`] serves captures from frames supplied up front, as RGB pixels, images or image files.
+//! It offers the capture functions of [`Display`](crate::Display), a pointer position and, with the
+//! `xrandr` feature, a monitor layout. As it implements [`Capturer`], code that is generic over it,
+//! e.g. the [`Recorder`](crate::record::Recorder), can be tested in CI containers without any X server.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::mock::MockDisplay;
+//! // A 2x1 display, showing a red and a blue pixel, followed by a white and a black one
+//! let display = MockDisplay::new(2, 1)
+//!     .frame(&[255, 0, 0, 0, 0, 255])
+//!     .frame(&[255, 255, 255, 0, 0, 0])
+//!     .pointer((1, 0));
+//! let first = display.capture().unwrap();
+//! assert_eq!(first.pixels().rgb_row(0).collect::<Vec<_>>(), [[255, 0, 0], [0, 0, 255]]);
+//! let second = display.capture_area((1, 0), (1, 1)).unwrap();
+//! assert_eq!(second.pixels().rgb_row(0).collect::<Vec<_>>(), [[0, 0, 0]]);
+//! // Every frame has been served
+//! assert!(display.capture().is_err());
+//! assert_eq!(display.root_mouse_position(), Some((1, 0)));
+//! ```
+//!
+
+use std::cell::Cell;
+use std::io;
+use std::path::Path;
+use crate::{Image, capture::{CaptureError, Capturer, Frame, Geometry}, codec::Encoding};
+
+/// A display serving captures from in-memory frames, see the [module documentation](self).
+///
+/// Each capture takes the next frame. Once every frame has been served, captures fail,
+/// unless the display is [looping](MockDisplay::looping). A display without any frame is black.
+pub struct MockDisplay {
+    pub width: u32,
+    pub height: u32,
+    /// The frames in BGRX
+    frames: Vec<Vec<u8>>,
+    next: Cell<usize>,
+    looping: bool,
+    pointer: Cell<Option<(i32, i32)>>,
+    #[cfg(feature = "xrandr")]
+    monitors: Vec<crate::monitor::Monitor>
+}
+
+impl MockDisplay {
+    /// A display of `width` x `height` pixels, without any frames
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            frames: Vec::new(),
+            next: Cell::new(0),
+            looping: false,
+            pointer: Cell::new(None),
+            #[cfg(feature = "xrandr")]
+            monitors: Vec::new()
+        }
+    }
+    /// Add a frame of tightly packed RGB pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rgb` doesn't hold exactly the pixels of the display.
+    pub fn frame(mut self, rgb: &[u8]) -> Self {
+        assert_eq!(rgb.len(), self.width as usize * self.height as usize * 3, "Frame doesn't have the size of the display");
+        self.frames.push(rgb.chunks_exact(3).flat_map(|rgb| [rgb[2], rgb[1], rgb[0], 0]).collect());
+        self
+    }
+    /// Add a copy of `image` as frame, e.g. a capture of a real display.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` doesn't have the size of the display.
+    pub fn image(mut self, image: &Image) -> Self {
+        let pixels = image.pixels();
+        assert_eq!((pixels.width(), pixels.height()), (self.width as usize, self.height as usize), "Image doesn't have the size of the display");
+        self.frames.push((0..pixels.height()).flat_map(|y| pixels.row(y).to_vec()).collect());
+        self
+    }
+    /// Add the image file at `path` as frame, in any format of the [built-in decoders](crate::codec)
+    pub fn open(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = std::fs::read(path)?;
+        let decoded = Encoding::detect(&data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported image format"))?
+            .decode(&data)?;
+        if (decoded.width, decoded.height) != (self.width as usize, self.height as usize) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image doesn't have the size of the display"));
+        }
+        Ok(self.frame(&decoded.data))
+    }
+    /// Start over at the first frame once every frame has been served
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+    /// Set the position of the pointer, relative to the root window
+    pub fn pointer(self, position: (i32, i32)) -> Self {
+        self.set_pointer(position);
+        self
+    }
+    /// Add a monitor named `name`, at `offset` (x, y) with `size` (width, height)
+    #[cfg(feature = "xrandr")]
+    pub fn monitor(mut self, name: impl Into<String>, offset: (i32, i32), size: (i32, i32), primary: bool) -> Self {
+        self.monitors.push(crate::monitor::Monitor {
+            name: name.into(),
+            x: offset.0,
+            y: offset.1,
+            width: size.0,
+            height: size.1,
+            // Physical size in millimeters, at 96 dpi
+            width_mm: size.0 * 254 / 960,
+            height_mm: size.1 * 254 / 960,
+            primary,
+            automatic: false,
+            outputs: Vec::new()
+        });
+        self
+    }
+
+    /// Take the next frame.
+    ///
+    /// # Errors
+    ///
+    /// Fails once every frame has been served, see [`MockDisplay::looping`].
+    #[allow(clippy::result_unit_err)]
+    pub fn capture(&self) -> Result<Image, ()> {
+        self.capture_area((0, 0), (self.width, self.height))
+    }
+    /// Take the area of `size` (width, height) at `offset` (x, y) of the next frame.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the area exceeds the display, like `XGetImage` does, or once every frame has been served.
+    #[allow(clippy::result_unit_err)]
+    pub fn capture_area(&self, offset: (u32, u32), size: (u32, u32)) -> Result<Image, ()> {
+        if offset.0 as u64 + size.0 as u64 > self.width as u64 || offset.1 as u64 + size.1 as u64 > self.height as u64 {
+            return Err(());
+        }
+        let mut image = Image::detached(size.0, size.1);
+        if self.frames.is_empty() {
+            return Ok(image);
+        }
+        let frame = self.next_frame().ok_or(())?;
+        let (stride, row) = (self.width as usize * 4, size.0 as usize * 4);
+        let target = unsafe { image.as_bytes_mut() };
+        for (y, target) in target.chunks_exact_mut(row).enumerate() {
+            let start = (offset.1 as usize + y) * stride + offset.0 as usize * 4;
+            target.copy_from_slice(&frame[start..start + row]);
+        }
+        Ok(image)
+    }
+    fn next_frame(&self) -> Option<&Vec<u8>> {
+        let mut index = self.next.get();
+        if index == self.frames.len() && self.looping {
+            index = 0;
+        }
+        let frame = self.frames.get(index)?;
+        self.next.set(index + 1);
+        Some(frame)
+    }
+    /// Whether every frame has been served, and captures fail
+    pub fn exhausted(&self) -> bool {
+        !self.looping && !self.frames.is_empty() && self.next.get() == self.frames.len()
+    }
+
+    /// The position of the pointer, as set through [`MockDisplay::pointer`]
+    pub fn root_mouse_position(&self) -> Option<(i32, i32)> {
+        self.pointer.get()
+    }
+    /// Move the pointer to `position`, e.g. while a test is running
+    pub fn set_pointer(&self, position: (i32, i32)) {
+        self.pointer.set(Some(position));
+    }
+    /// The monitors added through [`MockDisplay::monitor`]
+    #[cfg(feature = "xrandr")]
+    pub fn monitors(&self) -> Vec<crate::monitor::Monitor> {
+        self.monitors.clone()
+    }
+}
+
+impl MockDisplay {
+    fn next_capture(&self) -> Result<Frame<'static>, CaptureError> {
+        if self.exhausted() {
+            return Err(CaptureError::Exhausted);
+        }
+        self.capture().map(Frame::Owned).map_err(|_| CaptureError::CaptureFailed)
+    }
+}
+
+impl Capturer for &MockDisplay {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        self.next_capture()
+    }
+    fn geometry(&self) -> Geometry {
+        Geometry::new((0, 0), (self.width, self.height))
+    }
+}
+
+impl Capturer for MockDisplay {
+    fn capture(&mut self) -> Result<Frame<'_>, CaptureError> {
+        self.next_capture()
+    }
+    fn geometry(&self) -> Geometry {
+        Geometry::new((0, 0), (self.width, self.height))
+    }
+}
+
+#[test]
+fn serves_frames_in_order() {
+    let mut display = MockDisplay::new(2, 2)
+        .frame(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12])
+        .frame(&[0; 12]);
+    let area = display.capture_area((1, 1), (1, 1)).unwrap();
+    assert_eq!(area.pixels().rgb_row(0).collect::<Vec<_>>(), [[10, 11, 12]]);
+    assert!(display.capture_area((1, 1), (2, 1)).is_err());
+
+    let frame = Capturer::capture(&mut display).unwrap();
+    assert_eq!(frame.pixels().rgb_row(1).next(), Some([0, 0, 0]));
+    drop(frame);
+    assert_eq!(Capturer::capture(&mut display).err(), Some(CaptureError::Exhausted));
+
+    let looping = MockDisplay::new(1, 1).frame(&[1, 2, 3]).looping();
+    for _ in 0..3 {
+        assert_eq!(looping.capture().unwrap().pixels().rgb_row(0).next(), Some([1, 2, 3]));
+    }
+}
+
+#[cfg(feature = "dlopen")]
+#[test]
+fn captures_without_libx11() {
+    // The library is loaded once per process, so the test runs again in a process where it is missing
+    if std::env::var_os("RXSCREEN_MISSING_LIBRARY").is_none() {
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "mock::captures_without_libx11"])
+            .env("RXSCREEN_MISSING_LIBRARY", crate::ffi::library::X11.name())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        return;
+    }
+    assert!(!crate::ffi::library::X11.available());
+    let display = MockDisplay::new(2, 1).frame(&[1, 2, 3, 4, 5, 6]);
+    let image = display.capture().unwrap();
+    assert_eq!(image.pixels().rgb_row(0).collect::<Vec<_>>(), [[1, 2, 3], [4, 5, 6]]);
+}
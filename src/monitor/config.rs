@@ -0,0 +1,507 @@
+//! Changing the display configuration, i.e. the mode, position, rotation and primary flag of outputs.
+
+use crate::{Display, trap::ErrorTrap, ffi::{XDisplay, XGetGeometry, XGrabServer, XSync, XUngrabServer, XWindow, xrandr::{*, Rotation as RotationBits}}};
+use super::{Reflection, Rotation, ScreenResources};
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// RandR 1.3 isn't available
+    NotAvailable,
+    /// No output has the given name
+    UnknownOutput(String),
+    /// The output doesn't support the requested mode, or has no mode at all
+    UnsupportedMode(String),
+    /// The CRTC driving the output can't rotate or reflect as requested
+    UnsupportedRotation(String),
+    /// No CRTC is free to drive the output
+    NoCrtc(String),
+    /// The outputs wouldn't fit into the screen sizes the server supports, or the server rejected the size
+    ScreenSize { width: u32, height: u32 },
+    /// The server rejected the configuration of the output, the previous configuration got restored
+    Failed(String)
+}
+
+/// Which mode an output should show
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModeChoice {
+    Id(RRMode),
+    /// The mode of this size with the highest refresh rate
+    Size(u32, u32)
+}
+
+/// The requested changes of an output
+#[derive(Debug, Clone, Default)]
+struct Change {
+    enabled: Option<bool>,
+    mode: Option<ModeChoice>,
+    position: Option<(i32, i32)>,
+    rotation: Option<Rotation>,
+    reflection: Option<Reflection>
+}
+
+/// The configuration of a CRTC, as passed to `XRRSetCrtcConfig`
+#[derive(Debug, Clone, PartialEq)]
+struct CrtcConfig {
+    crtc: RRCrtc,
+    x: i32,
+    y: i32,
+    /// `None` disables the CRTC
+    mode: Option<RRMode>,
+    rotation: RotationBits,
+    outputs: Vec<RROutput>
+}
+
+/// The configuration to set, computed up front so that invalid changes fail before anything is changed
+#[derive(Debug, Clone, PartialEq)]
+struct Plan {
+    crtcs: Vec<CrtcConfig>,
+    size: (u32, u32),
+    primary: RROutput
+}
+
+/// Changes the display configuration at once, see [`Display::configure`]
+pub struct ConfigBuilder<'a> {
+    display: &'a mut Display,
+    changes: Vec<(String, Change)>,
+    primary: Option<String>
+}
+
+impl Display {
+    /// Change the configuration of the outputs, e.g. to switch to a resolution before taking reference screenshots.
+    /// ```rust,no_run
+    /// # use rxscreen::{Display, monitor::Rotation};
+    /// if let Ok(mut display) = Display::new(":0.0") {
+    ///     display.configure()
+    ///         .resolution("HDMI-1", (1920, 1080))
+    ///         .primary("HDMI-1")
+    ///         .resolution("DP-1", (1280, 1024))
+    ///         .position("DP-1", (1920, 0))
+    ///         .rotation("DP-1", Rotation::Left)
+    ///         .disable("eDP-1")
+    ///         .apply()
+    ///         .unwrap();
+    ///     assert_eq!((display.width, display.height), (1920 + 1024, 1280));
+    /// }
+    /// ```
+    /// The changes are applied transactionally by [`ConfigBuilder::apply`]. Outputs that aren't mentioned keep their configuration.
+    pub fn configure(&mut self) -> ConfigBuilder<'_> {
+        ConfigBuilder { display: self, changes: Vec::new(), primary: None }
+    }
+}
+
+impl<'a> ConfigBuilder<'a> {
+    fn change(&mut self, output: &str) -> &mut Change {
+        let index = match self.changes.iter().position(|(name, _)| name == output) {
+            Some(index) => index,
+            None => {
+                self.changes.push((output.to_owned(), Change::default()));
+                self.changes.len() - 1
+            }
+        };
+        &mut self.changes[index].1
+    }
+    /// Show the mode `mode` (see [`Output::modes`](super::Output::modes)) on `output`, enabling it
+    pub fn mode(mut self, output: &str, mode: RRMode) -> Self {
+        self.change(output).mode = Some(ModeChoice::Id(mode));
+        self.enable(output)
+    }
+    /// Show the mode of `size` (width, height) with the highest refresh rate on `output`, enabling it
+    pub fn resolution(mut self, output: &str, size: (u32, u32)) -> Self {
+        self.change(output).mode = Some(ModeChoice::Size(size.0, size.1));
+        self.enable(output)
+    }
+    /// Move `output` to `position` (x, y) of the screen, enabling it
+    pub fn position(mut self, output: &str, position: (i32, i32)) -> Self {
+        self.change(output).position = Some(position);
+        self.enable(output)
+    }
+    /// Rotate `output`, enabling it
+    pub fn rotation(mut self, output: &str, rotation: Rotation) -> Self {
+        self.change(output).rotation = Some(rotation);
+        self.enable(output)
+    }
+    /// Reflect `output`, enabling it
+    pub fn reflection(mut self, output: &str, reflection: Reflection) -> Self {
+        self.change(output).reflection = Some(reflection);
+        self.enable(output)
+    }
+    /// Make `output` the primary one
+    pub fn primary(mut self, output: &str) -> Self {
+        self.primary = Some(output.to_owned());
+        self
+    }
+    /// Enable `output`, in its preferred mode at the origin of the screen unless set otherwise
+    pub fn enable(mut self, output: &str) -> Self {
+        self.change(output).enabled = Some(true);
+        self
+    }
+    /// Disable `output`
+    pub fn disable(mut self, output: &str) -> Self {
+        *self.change(output) = Change { enabled: Some(false), ..Change::default() };
+        self
+    }
+
+    /// Apply every change at once, while the server is grabbed.
+    ///
+    /// # Errors
+    ///
+    /// Invalid changes, e.g. an unsupported mode, fail before anything is changed.
+    /// If the server rejects a change, the previous configuration of every CRTC, the screen size and
+    /// the primary output are restored, and [`ConfigError::Failed`] names the output, or
+    /// [`ConfigError::ScreenSize`] the rejected size of the screen.
+    pub fn apply(self) -> Result<(), ConfigError> {
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XRANDR.available() {
+            return Err(ConfigError::NotAvailable);
+        }
+        let (connection, window) = (self.display.connection, self.display.window);
+        let (mut min_width, mut min_height, mut max_width, mut max_height) = (0, 0, 0, 0);
+        if unsafe { XRRGetScreenSizeRange(connection, window, &mut min_width, &mut min_height, &mut max_width, &mut max_height) } == 0 {
+            return Err(ConfigError::NotAvailable);
+        }
+
+        let result = unsafe {
+            // Rejected requests fail the transition, instead of terminating the process
+            let trap = ErrorTrap::new(connection);
+            XGrabServer(connection);
+            // Planned from, and restored to, the configuration no other client can change until the server is ungrabbed
+            let resources = XRRGetScreenResourcesCurrent(connection, window);
+            let result = match resources.is_null() {
+                true => Err(ConfigError::NotAvailable),
+                false => {
+                    let before = self.display.read_screen_resources(resources);
+                    plan(&before, &self.changes, self.primary.as_deref()).and_then(|plan| {
+                        let size = (plan.size.0.max(min_width as u32), plan.size.1.max(min_height as u32));
+                        if size.0 > max_width as u32 || size.1 > max_height as u32 {
+                            return Err(ConfigError::ScreenSize { width: size.0, height: size.1 });
+                        }
+                        let plan = Plan { size, ..plan };
+                        let previous = Plan {
+                            crtcs: before.crtcs.iter().map(CrtcConfig::current).collect(),
+                            size: root_size(connection, window),
+                            primary: before.outputs.iter().find(|output| output.primary).map_or(0, |output| output.id)
+                        };
+                        let mut server = Grabbed { connection, window, resources, trap: &trap };
+                        transition(&mut server, &previous, &plan).map_err(|failure| {
+                            let output = match failure {
+                                Failure::Crtc(crtc) => plan.crtcs.iter().find(|config| config.crtc == crtc)
+                                    .and_then(|config| config.outputs.first().copied()),
+                                Failure::ScreenSize => return ConfigError::ScreenSize { width: size.0, height: size.1 },
+                                Failure::Primary => Some(plan.primary)
+                            };
+                            let name = output.and_then(|output| before.output(output)).map_or_else(String::new, |output| output.name.clone());
+                            ConfigError::Failed(name)
+                        })
+                    })
+                }
+            };
+            if !resources.is_null() {
+                XRRFreeScreenResources(resources);
+            }
+            XUngrabServer(connection);
+            XSync(connection, 0);
+            result
+        };
+        let (width, height) = result?;
+        self.display.width = width;
+        self.display.height = height;
+        Ok(())
+    }
+}
+
+impl CrtcConfig {
+    fn current(crtc: &super::Crtc) -> Self {
+        Self {
+            crtc: crtc.id,
+            x: crtc.x,
+            y: crtc.y,
+            mode: crtc.mode,
+            rotation: crtc.rotation,
+            outputs: crtc.outputs.clone()
+        }
+    }
+}
+
+/// The current size of the root window, rather than the one the display cached when it was opened
+unsafe fn root_size(connection: XDisplay, window: XWindow) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    XGetGeometry(connection, window, &mut 0, &mut 0, &mut 0, &mut width, &mut height, &mut 0, &mut 0);
+    (width, height)
+}
+
+/// The request the server rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Failure {
+    Crtc(RRCrtc),
+    ScreenSize,
+    Primary
+}
+
+/// The requests setting a configuration, each returning whether the server accepted it
+trait Server {
+    fn disable_crtc(&mut self, crtc: RRCrtc) -> bool;
+    fn set_crtc(&mut self, crtc: &CrtcConfig) -> bool;
+    fn set_screen_size(&mut self, size: (u32, u32)) -> bool;
+    fn set_primary(&mut self, output: RROutput) -> bool;
+}
+
+/// The grabbed server, syncing after every request to check for trapped errors
+struct Grabbed<'t> {
+    connection: XDisplay,
+    window: XWindow,
+    resources: *mut XRRScreenResources,
+    trap: &'t ErrorTrap
+}
+
+impl Server for Grabbed<'_> {
+    fn disable_crtc(&mut self, crtc: RRCrtc) -> bool {
+        let status = unsafe { XRRSetCrtcConfig(self.connection, self.resources, crtc, 0, 0, 0, 0, 1, std::ptr::null(), 0) };
+        !self.trap.failed() && status == 0
+    }
+    fn set_crtc(&mut self, crtc: &CrtcConfig) -> bool {
+        let status = unsafe {
+            XRRSetCrtcConfig(self.connection, self.resources, crtc.crtc, 0, crtc.x, crtc.y, crtc.mode.unwrap_or(0), crtc.rotation,
+                crtc.outputs.as_ptr(), crtc.outputs.len() as i32)
+        };
+        !self.trap.failed() && status == 0
+    }
+    fn set_screen_size(&mut self, size: (u32, u32)) -> bool {
+        // Physical size in millimeters, at 96 dpi
+        unsafe { XRRSetScreenSize(self.connection, self.window, size.0 as i32, size.1 as i32, (size.0 * 254 / 960) as i32, (size.1 * 254 / 960) as i32) };
+        !self.trap.failed()
+    }
+    fn set_primary(&mut self, output: RROutput) -> bool {
+        unsafe { XRRSetOutputPrimary(self.connection, self.window, output) };
+        !self.trap.failed()
+    }
+}
+
+/// Change the configuration from `previous` to `plan`, restoring `previous` if the server rejects any request.
+/// Returns the size of the screen afterwards.
+fn transition(server: &mut impl Server, previous: &Plan, plan: &Plan) -> Result<(u32, u32), Failure> {
+    let changed: Vec<&CrtcConfig> = plan.crtcs.iter().filter(|crtc| !previous.crtcs.contains(crtc)).collect();
+    let resize = !changed.is_empty() && plan.size != previous.size;
+    let result = set(server, &changed, resize.then_some(plan.size), plan.primary);
+    if result.is_err() {
+        // Restore the previous configuration of every CRTC that may have changed
+        let restore: Vec<&CrtcConfig> = previous.crtcs.iter()
+            .filter(|crtc| changed.iter().any(|changed| changed.crtc == crtc.crtc))
+            .collect();
+        let _ = set(server, &restore, resize.then_some(previous.size), previous.primary);
+    }
+    result.map(|_| if resize { plan.size } else { previous.size })
+}
+
+/// Set `crtcs`, first disabling them so that the screen can be resized to `size` in between,
+/// and make `primary` the primary output.
+fn set(server: &mut impl Server, crtcs: &[&CrtcConfig], size: Option<(u32, u32)>, primary: RROutput) -> Result<(), Failure> {
+    for crtc in crtcs {
+        if !server.disable_crtc(crtc.crtc) {
+            return Err(Failure::Crtc(crtc.crtc));
+        }
+    }
+    if size.is_some_and(|size| !server.set_screen_size(size)) {
+        return Err(Failure::ScreenSize);
+    }
+    for crtc in crtcs.iter().filter(|crtc| crtc.mode.is_some()) {
+        if !server.set_crtc(crtc) {
+            return Err(Failure::Crtc(crtc.crtc));
+        }
+    }
+    match server.set_primary(primary) {
+        true => Ok(()),
+        false => Err(Failure::Primary)
+    }
+}
+
+/// Compute the configuration of every CRTC after `changes`, and the screen size covering them
+fn plan(resources: &ScreenResources, changes: &[(String, Change)], primary: Option<&str>) -> Result<Plan, ConfigError> {
+    let mut crtcs: Vec<CrtcConfig> = resources.crtcs.iter().map(CrtcConfig::current).collect();
+    for (name, change) in changes {
+        let output = resources.output_by_name(name).ok_or_else(|| ConfigError::UnknownOutput(name.clone()))?;
+        let current = crtcs.iter().position(|crtc| crtc.outputs.contains(&output.id));
+
+        if change.enabled == Some(false) {
+            if let Some(index) = current {
+                let crtc = &mut crtcs[index];
+                crtc.outputs.retain(|&id| id != output.id);
+                if crtc.outputs.is_empty() {
+                    *crtc = CrtcConfig { crtc: crtc.crtc, x: 0, y: 0, mode: None, rotation: 1, outputs: vec![] };
+                }
+            }
+            continue;
+        }
+
+        let index = match current {
+            Some(index) => index,
+            None => crtcs.iter().position(|crtc| crtc.outputs.is_empty() && output.crtcs.contains(&crtc.crtc))
+                .ok_or_else(|| ConfigError::NoCrtc(name.clone()))?
+        };
+        let crtc = &mut crtcs[index];
+        let mode = match change.mode {
+            Some(ModeChoice::Id(id)) => output.modes.contains(&id).then_some(id),
+            Some(ModeChoice::Size(width, height)) => output.modes.iter()
+                .filter_map(|&id| resources.mode(id))
+                .filter(|mode| (mode.width, mode.height) == (width, height))
+                .max_by(|a, b| a.refresh_rate().total_cmp(&b.refresh_rate()))
+                .map(|mode| mode.id),
+            None => crtc.mode.or_else(|| output.preferred_modes().first().or(output.modes.first()).copied())
+        };
+        crtc.mode = Some(mode.ok_or_else(|| ConfigError::UnsupportedMode(name.clone()))?);
+        if let Some((x, y)) = change.position {
+            crtc.x = x;
+            crtc.y = y;
+        }
+        let rotation = change.rotation.map_or(crtc.rotation & 0xf, Rotation::bits);
+        let reflection = change.reflection.map_or(crtc.rotation & 0x30, Reflection::bits);
+        crtc.rotation = rotation | reflection;
+        if resources.crtc(crtc.crtc).is_some_and(|info| info.rotations & crtc.rotation != crtc.rotation) {
+            return Err(ConfigError::UnsupportedRotation(name.clone()));
+        }
+        if !crtc.outputs.contains(&output.id) {
+            crtc.outputs.push(output.id);
+        }
+    }
+
+    let mut size = (0, 0);
+    for crtc in &crtcs {
+        let Some(mode) = crtc.mode.and_then(|mode| resources.mode(mode)) else { continue };
+        let (width, height) = match Rotation::from_bits(crtc.rotation) {
+            Rotation::Left | Rotation::Right => (mode.height, mode.width),
+            Rotation::Normal | Rotation::Inverted => (mode.width, mode.height)
+        };
+        size.0 = size.0.max(crtc.x.max(0) as u32 + width);
+        size.1 = size.1.max(crtc.y.max(0) as u32 + height);
+    }
+    let primary = match primary {
+        Some(name) => resources.output_by_name(name).ok_or_else(|| ConfigError::UnknownOutput(name.to_owned()))?.id,
+        None => resources.outputs.iter().find(|output| output.primary).map_or(0, |output| output.id)
+    };
+    Ok(Plan { crtcs, size, primary })
+}
+
+#[test]
+fn plans_configurations_before_applying_them() {
+    use super::{ConnectionStatus, Crtc, Mode, Output};
+    let mode = |id, width, height, v_total| Mode {
+        id, name: format!("{}x{}", width, height), width, height,
+        dot_clock: 148_500_000, h_total: 2200, v_total, flags: 0
+    };
+    let output = |id, name: &str, crtc, modes: Vec<RRMode>| Output {
+        id, name: name.into(), crtc, status: ConnectionStatus::Connected, width_mm: 0, height_mm: 0,
+        crtcs: vec![10, 11], clones: vec![], modes, preferred: 1, primary: id == 1
+    };
+    let crtc = |id, mode, outputs| Crtc {
+        id, x: 0, y: 0, width: 1920, height: 1080, mode, rotation: 1, outputs, possible: vec![1, 2], rotations: 0xf
+    };
+    let resources = ScreenResources {
+        outputs: vec![output(1, "HDMI-1", Some(10), vec![100, 101, 102]), output(2, "DP-1", None, vec![101])],
+        crtcs: vec![crtc(10, Some(100), vec![1]), crtc(11, None, vec![])],
+        // 1920x1080 at 60 Hz, and 1280x720 at 50 and 60 Hz
+        modes: vec![mode(100, 1920, 1080, 1125), mode(101, 1280, 720, 1350), mode(102, 1280, 720, 1125)],
+        config_timestamp: 0
+    };
+    let change = |name: &str, change: Change| (name.to_owned(), change);
+
+    let planned = plan(&resources, &[
+        change("HDMI-1", Change { mode: Some(ModeChoice::Size(1280, 720)), ..Change::default() }),
+        change("DP-1", Change { enabled: Some(true), position: Some((1280, 0)), rotation: Some(Rotation::Left), ..Change::default() })
+    ], Some("DP-1")).unwrap();
+    assert_eq!(planned.crtcs, [
+        CrtcConfig { crtc: 10, x: 0, y: 0, mode: Some(102), rotation: 1, outputs: vec![1] },
+        CrtcConfig { crtc: 11, x: 1280, y: 0, mode: Some(101), rotation: 2, outputs: vec![2] }
+    ]);
+    assert_eq!((planned.size, planned.primary), ((1280 + 720, 1280), 2));
+
+    let disabled = plan(&resources, &[change("HDMI-1", Change { enabled: Some(false), ..Change::default() })], None).unwrap();
+    assert_eq!((disabled.crtcs[0].mode, disabled.size, disabled.primary), (None, (0, 0), 1));
+
+    assert_eq!(plan(&resources, &[change("VGA-1", Change::default())], None), Err(ConfigError::UnknownOutput("VGA-1".into())));
+    let unsupported = Change { mode: Some(ModeChoice::Size(640, 480)), ..Change::default() };
+    assert_eq!(plan(&resources, &[change("HDMI-1", unsupported)], None), Err(ConfigError::UnsupportedMode("HDMI-1".into())));
+}
+
+/// A request to a [`FakeServer`]
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+enum Request {
+    Disable(RRCrtc),
+    Set(RRCrtc, RRMode),
+    Size(u32, u32),
+    Primary(RROutput)
+}
+
+/// A server recording the requests of a transition, rejecting `reject`
+#[cfg(test)]
+struct FakeServer {
+    requests: Vec<Request>,
+    reject: Option<Request>
+}
+
+#[cfg(test)]
+impl FakeServer {
+    fn record(&mut self, request: Request) -> bool {
+        let accepted = self.reject.as_ref() != Some(&request);
+        self.requests.push(request);
+        accepted
+    }
+}
+
+#[cfg(test)]
+impl Server for FakeServer {
+    fn disable_crtc(&mut self, crtc: RRCrtc) -> bool {
+        self.record(Request::Disable(crtc))
+    }
+    fn set_crtc(&mut self, crtc: &CrtcConfig) -> bool {
+        self.record(Request::Set(crtc.crtc, crtc.mode.unwrap_or(0)))
+    }
+    fn set_screen_size(&mut self, size: (u32, u32)) -> bool {
+        self.record(Request::Size(size.0, size.1))
+    }
+    fn set_primary(&mut self, output: RROutput) -> bool {
+        self.record(Request::Primary(output))
+    }
+}
+
+#[test]
+fn restores_the_previous_configuration_on_failure() {
+    use Request::*;
+    let previous = Plan {
+        crtcs: vec![
+            CrtcConfig { crtc: 10, x: 0, y: 0, mode: Some(100), rotation: 1, outputs: vec![1] },
+            CrtcConfig { crtc: 11, x: 0, y: 0, mode: None, rotation: 1, outputs: vec![] }
+        ],
+        size: (1920, 1080),
+        primary: 1
+    };
+    let plan = Plan {
+        crtcs: vec![
+            CrtcConfig { crtc: 10, x: 0, y: 0, mode: Some(102), rotation: 1, outputs: vec![1] },
+            CrtcConfig { crtc: 11, x: 1280, y: 0, mode: Some(101), rotation: 2, outputs: vec![2] }
+        ],
+        size: (2000, 1280),
+        primary: 2
+    };
+    let run = |reject, plan: &Plan| {
+        let mut server = FakeServer { requests: vec![], reject };
+        let result = transition(&mut server, &previous, plan);
+        (result, server.requests)
+    };
+
+    let forward = [Disable(10), Disable(11), Size(2000, 1280), Set(10, 102), Set(11, 101), Primary(2)];
+    assert_eq!(run(None, &plan), (Ok((2000, 1280)), forward.to_vec()));
+
+    // Every changed CRTC is disabled and the previous ones set again, at the previous size
+    let (result, requests) = run(Some(Set(11, 101)), &plan);
+    assert_eq!(result, Err(Failure::Crtc(11)));
+    assert_eq!(requests[..5], forward[..5]);
+    assert_eq!(requests[5..], [Disable(10), Disable(11), Size(1920, 1080), Set(10, 100), Primary(1)]);
+
+    let (result, requests) = run(Some(Size(2000, 1280)), &plan);
+    assert_eq!(result, Err(Failure::ScreenSize));
+    assert_eq!(requests[3..], [Disable(10), Disable(11), Size(1920, 1080), Set(10, 100), Primary(1)]);
+
+    // Without changed CRTCs, the screen keeps its size
+    let unchanged = Plan { crtcs: previous.crtcs.clone(), size: (1920, 1200), primary: 1 };
+    assert_eq!(run(None, &unchanged), (Ok((1920, 1080)), vec![Primary(1)]));
+}
@@ -0,0 +1,211 @@
+//! 
+//! Exposes the X11 `xrandr` extension.
+//!
+//! This module contains the interface to the xrandr x11 extension
+//! which allows for querying monitors connected to the display, and the outputs, CRTCs and modes
+//! behind them (see [`ScreenResources`]), and for changing their configuration (see [`Display::configure`]).
+//!
+//! [xrandr](https://en.wikipedia.org/wiki/Xrandr)
+//! ```rust
+//! # use rxscreen::Display;
+//! if let Ok(display) = Display::new(":0.0") {
+//!    let monitors = display.monitors();
+//!    for monitor in monitors {
+//!        println!("Monitor: {}", monitor.name());
+//!        println!("\tPrimary: {}", monitor.primary());
+//!        println!("\tSize: {}x{}", monitor.width(), monitor.height());
+//!        println!("\tPosition: {}x{}", monitor.x(), monitor.y());
+//!    }
+//! }
+//!
+
+
+use crate::{Display, ffi::{xrandr::{*}, XGetAtomName, XInternAtom}};
+
+mod config;
+mod resources;
+
+pub use config::{ConfigBuilder, ConfigError};
+pub use resources::{ConnectionStatus, Crtc, Mode, Output, Reflection, Rotation, ScreenResources};
+
+impl Display {
+    #[cfg(feature = "xrandr")]
+    /// Query every monitor connected to the display
+    /// ```rust
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///    let monitors = display.monitors();
+    ///    for monitor in monitors {
+    ///        println!("Monitor: {}", monitor.name());
+    ///        println!("\tPrimary: {}", monitor.primary());
+    ///        println!("\tSize: {}x{}", monitor.width(), monitor.height());
+    ///        println!("\tPosition: {}x{}", monitor.x(), monitor.y());
+    ///    }
+    /// }
+    /// ```
+    /// With the `dlopen` feature, no monitors are reported if `libXrandr.so.2` couldn't be loaded.
+    pub fn monitors(&self) -> Vec<Monitor> {
+        use std::ffi::CStr;
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XRANDR.available() {
+            return vec![];
+        }
+        unsafe {
+            let mut monitor_count = 0;
+            let mut monitors = vec![];
+            let ptr = XRRGetMonitors(self.connection, self.window, 0, &mut monitor_count);
+
+            for i in 0..monitor_count {
+                let information = &*ptr.offset(i as isize);
+                let mon = Monitor {
+                    name: CStr::from_ptr(XGetAtomName(self.connection, information.name)).to_str().unwrap().to_owned(),
+                    x: information.x,
+                    y: information.y,
+                    width: information.width,
+                    height: information.height,
+                    width_mm: information.mwidth,
+                    height_mm: information.mheight,
+                    primary: information.primary == 1,
+                    automatic: information.automatic == 1,
+                    outputs: match information.outputs.is_null() {
+                        true => vec![],
+                        false => std::slice::from_raw_parts(information.outputs, information.noutput as usize).to_vec()
+                    }
+                };
+                monitors.push(mon);
+            }
+            XRRFreeMonitors(ptr);
+            monitors
+        }
+    }
+    /// Report changes of the screen configuration as [`Event::ScreenChange`](crate::event::Event::ScreenChange),
+    /// and changes of CRTCs, outputs, providers and their properties as [`Event::RandrNotify`](crate::event::Event::RandrNotify).
+    ///
+    /// With the `dlopen` feature, nothing is reported if `libXrandr.so.2` couldn't be loaded.
+    pub fn watch_screen_changes(&self) {
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XRANDR.available() {
+            return;
+        }
+        // Also initializes the extension in Xlib, which only then translates its events
+        let mask = RRScreenChangeNotifyMask | RRCrtcChangeNotifyMask | RROutputChangeNotifyMask | RROutputPropertyNotifyMask
+            | RRProviderChangeNotifyMask | RRProviderPropertyNotifyMask | RRResourceChangeNotifyMask;
+        unsafe { XRRSelectInput(self.connection, self.window, mask) };
+    }
+    /// Define a monitor named `name` covering `size` (width, height) at `offset` (x, y),
+    /// replacing any monitor of the same name, as `xrandr --setmonitor` does.
+    ///
+    /// Returns whether the server accepted the monitor, which it doesn't e.g. for an empty name.
+    pub(crate) fn set_monitor(&self, name: &str, offset: (i32, i32), size: (i32, i32), primary: bool) -> bool {
+        let Ok(name) = std::ffi::CString::new(name) else { return false };
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XRANDR.available() {
+            return false;
+        }
+        let trap = crate::trap::ErrorTrap::new(self.connection);
+        unsafe {
+            let monitor = XRRMonitorInfo {
+                name: XInternAtom(self.connection, name.as_ptr(), 0),
+                primary: primary as i32,
+                automatic: 0,
+                noutput: 0,
+                x: offset.0,
+                y: offset.1,
+                width: size.0,
+                height: size.1,
+                // Physical size in millimeters, at 96 dpi
+                mwidth: size.0 * 254 / 960,
+                mheight: size.1 * 254 / 960,
+                outputs: std::ptr::null()
+            };
+            XRRSetMonitor(self.connection, self.window, &monitor);
+        }
+        !trap.failed()
+    }
+}
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    pub(crate) name: String,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) width_mm: i32,
+    pub(crate) height_mm: i32,
+    pub(crate) primary: bool,
+    pub(crate) automatic: bool,
+    pub(crate) outputs: Vec<RROutput>
+}
+impl Monitor {
+    pub fn name(&self) -> &str {
+        &self.name[..]
+    }
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+    /// The physical width in millimeters
+    pub fn width_mm(&self) -> i32 {
+        self.width_mm
+    }
+    /// The physical height in millimeters
+    pub fn height_mm(&self) -> i32 {
+        self.height_mm
+    }
+    pub fn primary(&self) -> bool {
+        self.primary
+    }
+    /// Whether the server created the monitor for an output, rather than a client through `XRRSetMonitor`
+    pub fn automatic(&self) -> bool {
+        self.automatic
+    }
+    /// The outputs showing the monitor, see [`ScreenResources::output`]
+    pub fn outputs(&self) -> &[RROutput] {
+        &self.outputs
+    }
+
+    #[cfg(feature = "mouse")]
+    /// Translates a position from Display::root_mouse_position() to a position relative to the monitor
+    pub fn mouse_to_local(&self, root_pos: (i32, i32)) -> Option<(i32, i32)> {
+        let local_pos = (root_pos.0 - self.x, root_pos.1 - self.y);
+        if local_pos.0 >= 0 && local_pos.0 <= self.width && local_pos.1 >= 0 && local_pos.1 <= self.height {
+            Some(local_pos)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn reports_screen_changes() {
+    use crate::event::Event;
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    display.watch_screen_changes();
+    unsafe {
+        XRRSetScreenSize(display.connection, display.window, 300, 200, 79, 53);
+        crate::ffi::XSync(display.connection, 0);
+    }
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        match display.poll_event() {
+            Some(Event::ScreenChange { root, width, height, .. }) => {
+                assert_eq!((root, width, height), (display.window, 300, 200));
+                return;
+            },
+            Some(_) => {},
+            None => std::thread::sleep(std::time::Duration::from_millis(10))
+        }
+    }
+    panic!("No screen change reported");
+}
@@ -0,0 +1,406 @@
+//! Outputs, CRTCs and modes of the screen, as reported by `XRRGetScreenResourcesCurrent`.
+
+use core::ffi::c_ulong;
+use std::ffi::CStr;
+use crate::{Display, ffi::{Time, xrandr::{*, Rotation as RotationBits}}};
+use super::Monitor;
+
+/// The rotation of a CRTC, counterclockwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Normal,
+    Left,
+    Inverted,
+    Right
+}
+
+impl Rotation {
+    pub(crate) fn bits(self) -> RotationBits {
+        match self {
+            Rotation::Normal => 1,
+            Rotation::Left => 2,
+            Rotation::Inverted => 4,
+            Rotation::Right => 8
+        }
+    }
+    pub(crate) fn from_bits(bits: RotationBits) -> Self {
+        match bits & 0xf {
+            2 => Rotation::Left,
+            4 => Rotation::Inverted,
+            8 => Rotation::Right,
+            _ => Rotation::Normal
+        }
+    }
+}
+
+/// The reflection of a CRTC, applied before its rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Reflection {
+    /// Mirrored horizontally
+    pub x: bool,
+    /// Mirrored vertically
+    pub y: bool
+}
+
+impl Reflection {
+    pub(crate) fn bits(self) -> RotationBits {
+        (self.x as RotationBits) << 4 | (self.y as RotationBits) << 5
+    }
+    pub(crate) fn from_bits(bits: RotationBits) -> Self {
+        Self { x: bits & 16 != 0, y: bits & 32 != 0 }
+    }
+}
+
+/// Whether a monitor is plugged into an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connected,
+    Disconnected,
+    Unknown
+}
+
+/// A video mode, i.e. a resolution with its timings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mode {
+    pub(crate) id: RRMode,
+    pub(crate) name: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// The pixel clock in Hz
+    pub(crate) dot_clock: c_ulong,
+    pub(crate) h_total: u32,
+    pub(crate) v_total: u32,
+    pub(crate) flags: c_ulong
+}
+
+impl Mode {
+    pub fn id(&self) -> RRMode {
+        self.id
+    }
+    /// The name, e.g. `1920x1080`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// The refresh rate in Hz, e.g. `59.94`, or 0 if the mode has no timings
+    pub fn refresh_rate(&self) -> f64 {
+        let mut v_total = self.v_total as f64;
+        if self.flags & RR_DoubleScan != 0 {
+            v_total *= 2.0;
+        }
+        if self.flags & RR_Interlace != 0 {
+            v_total /= 2.0;
+        }
+        match self.h_total as f64 * v_total {
+            total if total > 0.0 => self.dot_clock as f64 / total,
+            _ => 0.0
+        }
+    }
+}
+
+/// A connector of the graphics card, e.g. `HDMI-1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub(crate) id: RROutput,
+    pub(crate) name: String,
+    pub(crate) crtc: Option<RRCrtc>,
+    pub(crate) status: ConnectionStatus,
+    pub(crate) width_mm: u32,
+    pub(crate) height_mm: u32,
+    pub(crate) crtcs: Vec<RRCrtc>,
+    pub(crate) clones: Vec<RROutput>,
+    pub(crate) modes: Vec<RRMode>,
+    /// The amount of preferred modes at the start of `modes`
+    pub(crate) preferred: usize,
+    pub(crate) primary: bool
+}
+
+impl Output {
+    pub fn id(&self) -> RROutput {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The CRTC driving the output, `None` if the output is disabled
+    pub fn crtc(&self) -> Option<RRCrtc> {
+        self.crtc
+    }
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+    pub fn connected(&self) -> bool {
+        self.status == ConnectionStatus::Connected
+    }
+    /// The physical width of the connected monitor in millimeters, 0 if unknown
+    pub fn width_mm(&self) -> u32 {
+        self.width_mm
+    }
+    /// The physical height of the connected monitor in millimeters, 0 if unknown
+    pub fn height_mm(&self) -> u32 {
+        self.height_mm
+    }
+    /// The CRTCs that can drive the output
+    pub fn crtcs(&self) -> &[RRCrtc] {
+        &self.crtcs
+    }
+    /// The outputs that can show the same content at the same time
+    pub fn clones(&self) -> &[RROutput] {
+        &self.clones
+    }
+    /// The modes the connected monitor supports
+    pub fn modes(&self) -> &[RRMode] {
+        &self.modes
+    }
+    /// The modes the connected monitor prefers, usually its native resolution
+    pub fn preferred_modes(&self) -> &[RRMode] {
+        &self.modes[..self.preferred.min(self.modes.len())]
+    }
+    pub fn primary(&self) -> bool {
+        self.primary
+    }
+}
+
+/// A scanout engine, showing an area of the screen in a mode on its outputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crtc {
+    pub(crate) id: RRCrtc,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) mode: Option<RRMode>,
+    pub(crate) rotation: RotationBits,
+    pub(crate) outputs: Vec<RROutput>,
+    pub(crate) possible: Vec<RROutput>,
+    pub(crate) rotations: RotationBits
+}
+
+impl Crtc {
+    pub fn id(&self) -> RRCrtc {
+        self.id
+    }
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+    /// The width on the screen, after rotation
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    /// The height on the screen, after rotation
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// The mode of the CRTC, `None` if it is disabled
+    pub fn mode(&self) -> Option<RRMode> {
+        self.mode
+    }
+    pub fn rotation(&self) -> Rotation {
+        Rotation::from_bits(self.rotation)
+    }
+    pub fn reflection(&self) -> Reflection {
+        Reflection::from_bits(self.rotation)
+    }
+    /// The outputs the CRTC drives
+    pub fn outputs(&self) -> &[RROutput] {
+        &self.outputs
+    }
+    /// The outputs the CRTC can drive
+    pub fn possible_outputs(&self) -> &[RROutput] {
+        &self.possible
+    }
+    pub fn supports_rotation(&self, rotation: Rotation) -> bool {
+        self.rotations & rotation.bits() != 0
+    }
+    pub fn supports_reflection(&self, reflection: Reflection) -> bool {
+        self.rotations & reflection.bits() == reflection.bits()
+    }
+}
+
+/// The outputs, CRTCs and modes of the screen, see [`Display::screen_resources`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenResources {
+    pub(crate) outputs: Vec<Output>,
+    pub(crate) crtcs: Vec<Crtc>,
+    pub(crate) modes: Vec<Mode>,
+    /// When the configuration was last changed, which changes have to name
+    pub(crate) config_timestamp: Time
+}
+
+impl ScreenResources {
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+    pub fn crtcs(&self) -> &[Crtc] {
+        &self.crtcs
+    }
+    pub fn modes(&self) -> &[Mode] {
+        &self.modes
+    }
+    pub fn output(&self, id: RROutput) -> Option<&Output> {
+        self.outputs.iter().find(|output| output.id == id)
+    }
+    /// The output named `name`, e.g. `HDMI-1`
+    pub fn output_by_name(&self, name: &str) -> Option<&Output> {
+        self.outputs.iter().find(|output| output.name == name)
+    }
+    pub fn crtc(&self, id: RRCrtc) -> Option<&Crtc> {
+        self.crtcs.iter().find(|crtc| crtc.id == id)
+    }
+    pub fn mode(&self, id: RRMode) -> Option<&Mode> {
+        self.modes.iter().find(|mode| mode.id == id)
+    }
+    /// The mode of the CRTC driving `output`
+    pub fn current_mode(&self, output: &Output) -> Option<&Mode> {
+        self.mode(self.crtc(output.crtc?)?.mode?)
+    }
+
+    /// The refresh rate of `monitor` in Hz, the highest of its outputs if they differ.
+    /// Monitors defined without outputs take the CRTC at their position.
+    pub fn refresh_rate(&self, monitor: &Monitor) -> Option<f64> {
+        let crtcs: Vec<&Crtc> = match monitor.outputs.is_empty() {
+            false => monitor.outputs.iter().filter_map(|&output| self.crtc(self.output(output)?.crtc?)).collect(),
+            true => self.crtcs.iter().filter(|crtc| (crtc.x, crtc.y) == (monitor.x, monitor.y)).collect()
+        };
+        crtcs.into_iter()
+            .filter_map(|crtc| self.mode(crtc.mode?))
+            .map(Mode::refresh_rate)
+            .filter(|&rate| rate > 0.0)
+            .reduce(f64::max)
+    }
+}
+
+impl Display {
+    /// Query the outputs, CRTCs and modes of the screen.
+    /// ```rust
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     if let Some(resources) = display.screen_resources() {
+    ///         for output in resources.outputs().iter().filter(|output| output.connected()) {
+    ///             println!("{}: {}x{} mm", output.name(), output.width_mm(), output.height_mm());
+    ///             if let Some(mode) = resources.current_mode(output) {
+    ///                 println!("\t{} at {:.2} Hz", mode.name(), mode.refresh_rate());
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    /// Returns `None` if the server lacks RandR 1.3, or with the `dlopen` feature, if `libXrandr.so.2` couldn't be loaded.
+    pub fn screen_resources(&self) -> Option<ScreenResources> {
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XRANDR.available() {
+            return None;
+        }
+        unsafe {
+            let resources = XRRGetScreenResourcesCurrent(self.connection, self.window);
+            if resources.is_null() {
+                return None;
+            }
+            let result = self.read_screen_resources(resources);
+            XRRFreeScreenResources(resources);
+            Some(result)
+        }
+    }
+    /// Read the outputs, CRTCs and modes of `resources`, which the caller frees
+    pub(crate) unsafe fn read_screen_resources(&self, resources: *mut XRRScreenResources) -> ScreenResources {
+        let info = &*resources;
+        let primary = XRRGetOutputPrimary(self.connection, self.window);
+
+        let modes = slice(info.modes, info.nmode).iter().map(|mode| Mode {
+            id: mode.id,
+            name: String::from_utf8_lossy(slice(mode.name as *const u8, mode.name_length as i32)).into_owned(),
+            width: mode.width,
+            height: mode.height,
+            dot_clock: mode.dot_clock,
+            h_total: mode.h_total,
+            v_total: mode.v_total,
+            flags: mode.mode_flags
+        }).collect();
+
+        let outputs = slice(info.outputs, info.noutput).iter().filter_map(|&id| {
+            let output = XRRGetOutputInfo(self.connection, resources, id);
+            if output.is_null() {
+                return None;
+            }
+            let o = &*output;
+            let result = Output {
+                id,
+                name: CStr::from_ptr(o.name).to_string_lossy().into_owned(),
+                crtc: (o.crtc != 0).then_some(o.crtc),
+                status: match o.connection {
+                    0 => ConnectionStatus::Connected,
+                    1 => ConnectionStatus::Disconnected,
+                    _ => ConnectionStatus::Unknown
+                },
+                width_mm: o.mm_width as u32,
+                height_mm: o.mm_height as u32,
+                crtcs: slice(o.crtcs, o.ncrtc).to_vec(),
+                clones: slice(o.clones, o.nclone).to_vec(),
+                modes: slice(o.modes, o.nmode).to_vec(),
+                preferred: o.npreferred.max(0) as usize,
+                primary: id == primary
+            };
+            XRRFreeOutputInfo(output);
+            Some(result)
+        }).collect();
+
+        let crtcs = slice(info.crtcs, info.ncrtc).iter().filter_map(|&id| {
+            let crtc = XRRGetCrtcInfo(self.connection, resources, id);
+            if crtc.is_null() {
+                return None;
+            }
+            let c = &*crtc;
+            let result = Crtc {
+                id,
+                x: c.x,
+                y: c.y,
+                width: c.width,
+                height: c.height,
+                mode: (c.mode != 0).then_some(c.mode),
+                rotation: c.rotation,
+                outputs: slice(c.outputs, c.noutput).to_vec(),
+                possible: slice(c.possible, c.npossible).to_vec(),
+                rotations: c.rotations
+            };
+            XRRFreeCrtcInfo(crtc);
+            Some(result)
+        }).collect();
+
+        let config_timestamp = info.config_timestamp;
+        ScreenResources { outputs, crtcs, modes, config_timestamp }
+    }
+}
+
+/// View the `length` elements at `data`, which may be null if there are none
+unsafe fn slice<'a, T>(data: *const T, length: i32) -> &'a [T] {
+    match data.is_null() || length <= 0 {
+        true => &[],
+        false => std::slice::from_raw_parts(data, length as usize)
+    }
+}
+
+#[test]
+fn computes_refresh_rates_and_rotations() {
+    let mode = |flags, v_total| Mode {
+        id: 1, name: "1920x1080".into(), width: 1920, height: 1080,
+        dot_clock: 148_500_000, h_total: 2200, v_total, flags
+    };
+    assert_eq!(mode(0, 1125).refresh_rate(), 60.0);
+    assert_eq!(mode(RR_Interlace, 1125).refresh_rate(), 120.0);
+    assert_eq!(mode(RR_DoubleScan, 1125).refresh_rate(), 30.0);
+    assert_eq!(mode(0, 0).refresh_rate(), 0.0);
+
+    for rotation in [Rotation::Normal, Rotation::Left, Rotation::Inverted, Rotation::Right] {
+        let reflection = Reflection { x: true, y: false };
+        let bits = rotation.bits() | reflection.bits();
+        assert_eq!((Rotation::from_bits(bits), Reflection::from_bits(bits)), (rotation, reflection));
+    }
+}
@@ -1,5 +1,4 @@
 use crate::Display;
-use core::ffi::{c_int, c_uint};
 
 impl Display {
     #[cfg(feature = "xrandr")]
@@ -13,6 +12,7 @@ impl Display {
     ///    println!("Mouse Pos: {}, {}", mouse_x, mouse_y);
     /// }
     pub fn root_mouse_position(&self) -> Option<(i32, i32)> {
+        use core::ffi::{c_int, c_uint};
         use crate::ffi::{mouse::XQueryPointer, XWindow};
         unsafe {
             let mut root: XWindow = 0;
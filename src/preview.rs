@@ -0,0 +1,208 @@
+//!
+//! Live preview of captures in a window.
+//!
+//! [`Preview`] opens a window on the display and shows images in it, scaled to fit the window
+//! while keeping their aspect ratio. With the `shm` feature, frames are presented through
+//! `XShmPutImage` whenever the X server supports it, and through `XPutImage` otherwise.
+//!
+//! # Usage
+//! ```rust,no_run
+//! # use rxscreen::{Display, preview::Preview};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     if let Ok(mut preview) = Preview::new(&display, (640, 360)) {
+//!         // Show the display inside the preview window, until the user closes it
+//!         while !preview.closed() {
+//!             let capture = display.capture().unwrap();
+//!             preview.show(&capture);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+
+use crate::{Display, Image, ffi::*};
+use crate::graphics::{GraphicsError, Window};
+use std::marker::PhantomData;
+
+/// The buffer frames are scaled into before they get sent to the X server
+enum Canvas<'a> {
+    Plain(Image, PhantomData<&'a Display>),
+    #[cfg(feature = "shm")]
+    Shared(crate::shm::SharedImage<'a>)
+}
+
+impl<'a> Canvas<'a> {
+    fn new(display: &'a Display, size: (u32, u32)) -> Self {
+        #[cfg(feature = "shm")]
+        if let Ok(image) = display.shm().area((0, 0), size).build_image() {
+            return Canvas::Shared(image);
+        }
+        Canvas::Plain(Image::empty(display, size.0, size.1), PhantomData)
+    }
+    fn image(&self) -> &Image {
+        match self {
+            Canvas::Plain(image, _) => image,
+            #[cfg(feature = "shm")]
+            Canvas::Shared(shared) => shared.image()
+        }
+    }
+    /// Take note of the completion of the previous frame, returning whether `event` was it
+    fn handle_event(&mut self, event: &crate::event::Event) -> bool {
+        match self {
+            Canvas::Plain(..) => {
+                let _ = event;
+                false
+            },
+            #[cfg(feature = "shm")]
+            Canvas::Shared(shared) => shared.handle_event(event)
+        }
+    }
+    /// Waits for the previous frame to be presented, before handing out the image.
+    fn image_mut(&mut self) -> &mut Image {
+        match self {
+            Canvas::Plain(image, _) => image,
+            #[cfg(feature = "shm")]
+            Canvas::Shared(shared) => shared.image_mut()
+        }
+    }
+}
+
+/// A window showing a stream of frames, see the [module documentation](self).
+pub struct Preview<'a> {
+    display: &'a Display,
+    window: Window<'a>,
+    canvas: Canvas<'a>
+}
+
+impl<'a> Preview<'a> {
+    /// Open a preview window of `size` (width, height) on `display`
+    pub fn new(display: &'a Display, size: (u32, u32)) -> Result<Self, GraphicsError> {
+        let window = display.new_window()
+            .title("rxscreen preview")
+            .size(size)
+            .build()?;
+        Ok(Self {
+            display,
+            canvas: Canvas::new(display, window.size()),
+            window
+        })
+    }
+
+    /// Show `image` in the window, scaled to fit while keeping its aspect ratio.
+    ///
+    /// Pending events of the display are processed before the frame is presented, so the preview
+    /// follows resizes of the window. Returns `false` once the window got closed.
+    pub fn show(&mut self, image: &Image) -> bool {
+        while let Some(event) = self.display.poll_event() {
+            // The completion of the previous frame may be among the events, which the canvas waits for otherwise
+            if !self.canvas.handle_event(&event) {
+                self.window.handle_event(&event);
+            }
+        }
+        if self.window.closed() {
+            return false;
+        }
+
+        let size = self.window.size();
+        let canvas = self.canvas.image();
+        if (canvas.width() as u32, canvas.height() as u32) != size {
+            self.canvas = Canvas::new(self.display, size);
+        }
+
+        scale_into(image, self.canvas.image_mut());
+        self.present();
+        true
+    }
+
+    /// Show every frame captured through `session` until the window got closed, or a capture failed.
+    #[cfg(feature = "shm")]
+    pub fn stream(&mut self, session: &mut crate::shm::SharedSession) {
+        while let Ok(frame) = session.capture() {
+            if !self.show(frame) {
+                break;
+            }
+        }
+    }
+
+    /// Whether the preview window has been closed by the user.
+    pub fn closed(&self) -> bool {
+        self.window.closed()
+    }
+
+    /// The window the preview is shown in
+    pub fn window(&self) -> &Window<'a> {
+        &self.window
+    }
+
+    fn present(&mut self) {
+        let (width, height) = self.window.size();
+        match &mut self.canvas {
+            Canvas::Plain(image, _) => unsafe {
+                XPutImage(self.display.connection, self.window.id(), self.window.gc, image.raw, 0, 0, 0, 0, width, height);
+                XFlush(self.display.connection);
+            },
+            // The next frame waits for the completion of this one, before it gets scaled into the canvas.
+            #[cfg(feature = "shm")]
+            Canvas::Shared(shared) => shared.put(self.window.id(), (0, 0))
+        }
+    }
+}
+
+/// Nearest-neighbour scale `source` into `target`, keeping the aspect ratio and centering it.
+/// The remaining area of `target` is filled black.
+fn scale_into(source: &Image, target: &mut Image) {
+    let (src_width, src_height) = (source.width().max(0) as usize, source.height().max(0) as usize);
+    let (dst_width, dst_height) = (target.width().max(0) as usize, target.height().max(0) as usize);
+    let (src_stride, dst_stride) = unsafe { ((*source.raw).bytes_per_line as usize, (*target.raw).bytes_per_line as usize) };
+    let src = unsafe { std::slice::from_raw_parts(source.as_ptr(), src_stride * src_height) };
+    let dst = unsafe { std::slice::from_raw_parts_mut((*target.raw).data as *mut u8, dst_stride * dst_height) };
+    scale_pixels(src, (src_width, src_height), src_stride, dst, (dst_width, dst_height), dst_stride);
+}
+
+fn scale_pixels(src: &[u8], src_size: (usize, usize), src_stride: usize, dst: &mut [u8], dst_size: (usize, usize), dst_stride: usize) {
+    dst.iter_mut().for_each(|byte| *byte = 0);
+    if src_size.0 == 0 || src_size.1 == 0 || dst_size.0 == 0 || dst_size.1 == 0 {
+        return;
+    }
+
+    // Fit the width first, and fall back to fitting the height if the result would be too tall
+    let (mut width, mut height) = (dst_size.0, src_size.1 * dst_size.0 / src_size.0);
+    if height > dst_size.1 {
+        width = src_size.0 * dst_size.1 / src_size.1;
+        height = dst_size.1;
+    }
+    let (left, top) = ((dst_size.0 - width) / 2, (dst_size.1 - height) / 2);
+
+    let columns = (0..width).map(|x| x * src_size.0 / width.max(1) * 4).collect::<Vec<usize>>();
+    for y in 0..height {
+        let src_row = &src[y * src_size.1 / height * src_stride..];
+        let dst_row = &mut dst[(top + y) * dst_stride + left * 4..];
+        for (x, column) in columns.iter().enumerate() {
+            dst_row[x * 4..x * 4 + 4].copy_from_slice(&src_row[*column..*column + 4]);
+        }
+    }
+}
+
+#[test]
+fn scales_and_letterboxes_frames() {
+    // 2x1 source, white and gray pixel, scaled into a 4x4 target
+    let src = [255, 255, 255, 0, 128, 128, 128, 0];
+    let mut dst = [1u8; 4 * 4 * 4];
+    scale_pixels(&src, (2, 1), 8, &mut dst, (4, 4), 16);
+
+    let row = |y: usize| &dst[y * 16..y * 16 + 16];
+    assert_eq!(row(0), &[0; 16]);
+    assert_eq!(row(1), &[255, 255, 255, 0, 255, 255, 255, 0, 128, 128, 128, 0, 128, 128, 128, 0]);
+    assert_eq!(row(2), row(1));
+    assert_eq!(row(3), &[0; 16]);
+}
+
+#[test]
+fn shows_consecutive_frames() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut preview = Preview::new(&display, (160, 120)).unwrap();
+    let capture = display.capture().unwrap();
+    for _ in 0..3 {
+        assert!(preview.show(&capture));
+    }
+}
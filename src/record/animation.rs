@@ -0,0 +1,175 @@
+//!
+//! Animated GIF and APNG clips.
+//!
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::codec::Pixels;
+use super::{apng::ApngWriter, gif::GifWriter};
+
+/// The formats of animated clips
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    /// Animated GIF, each frame is reduced to a palette of 256 colors
+    Gif,
+    /// Animated PNG in full color, the frames are kept in memory until the clip is finished
+    Apng
+}
+
+impl AnimationFormat {
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<AnimationFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    Gif(GifWriter<W>),
+    Apng(ApngWriter<W>)
+}
+
+/// A frame that is written once it is known how long it is shown
+struct Pending {
+    rect: (usize, usize, usize, usize),
+    pixels: Vec<u8>
+}
+
+/// Encodes captured frames into an animated clip.
+///
+/// Only the rectangle that changed since the previous frame is stored, and frames without any change
+/// are skipped entirely. Each frame is shown until the timestamp of the next stored frame, so the clip
+/// plays back at the speed it was captured at.
+///
+/// ```rust,no_run
+/// # use rxscreen::{Display, record::{Animation, AnimationFormat}};
+/// # use std::time::Instant;
+/// if let Ok(display) = Display::new(":0.0") {
+///     let file = std::fs::File::create("./clip.gif").unwrap();
+///     let mut clip = Animation::new(std::io::BufWriter::new(file), AnimationFormat::Gif, (640, 480)).unwrap();
+///     let start = Instant::now();
+///     for _ in 0..50 {
+///         let capture = display.capture_area((0, 0), (640, 480)).unwrap();
+///         clip.push(&capture.pixels(), start.elapsed()).unwrap();
+///     }
+///     clip.finish(start.elapsed()).unwrap();
+/// }
+/// ```
+pub struct Animation<W: Write> {
+    encoder: Encoder<W>,
+    size: (usize, usize),
+    /// The RGB pixels of the clip, as shown after the last frame
+    canvas: Vec<u8>,
+    pending: Option<Pending>
+}
+
+impl<W: Write> Animation<W> {
+    /// Start a clip of `size` (width, height) in `format`, written to `writer`
+    pub fn new(writer: W, format: AnimationFormat, size: (u32, u32)) -> io::Result<Self> {
+        let size = (size.0 as usize, size.1 as usize);
+        let encoder = match format {
+            AnimationFormat::Gif => Encoder::Gif(GifWriter::new(writer, size)?),
+            AnimationFormat::Apng => Encoder::Apng(ApngWriter::new(writer, size)?)
+        };
+        Ok(Self {
+            encoder,
+            size,
+            canvas: Vec::new(),
+            pending: None
+        })
+    }
+
+    /// Add a frame captured at `timestamp`, relative to the start of the clip.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `pixels` doesn't have the size of the clip, or the previous frame couldn't be written.
+    pub fn push(&mut self, pixels: &Pixels, timestamp: Duration) -> io::Result<()> {
+        if (pixels.width(), pixels.height()) != self.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frame doesn't have the size of the animation"));
+        }
+        let (width, height) = self.size;
+        let mut frame = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            pixels.rgb_row(y).for_each(|rgb| frame.extend_from_slice(&rgb));
+        }
+
+        let rect = if self.canvas.is_empty() {
+            Some((0, 0, width, height))
+        } else {
+            changed_rect(&self.canvas, &frame, self.size)
+        };
+        let Some(rect) = rect else {
+            return Ok(());
+        };
+
+        if let Some(pending) = self.pending.take() {
+            self.write(pending, timestamp)?;
+        }
+        let mut cropped = Vec::with_capacity(rect.2 * rect.3 * 3);
+        for y in rect.1..rect.1 + rect.3 {
+            cropped.extend_from_slice(&frame[(y * width + rect.0) * 3..(y * width + rect.0 + rect.2) * 3]);
+        }
+        self.pending = Some(Pending { rect, pixels: cropped });
+        self.canvas = frame;
+        Ok(())
+    }
+
+    /// Write the last frame, shown until `end`, and finish the clip.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frames couldn't be written, or an APNG clip has no frame at all.
+    pub fn finish(mut self, end: Duration) -> io::Result<()> {
+        if let Some(pending) = self.pending.take() {
+            self.write(pending, end)?;
+        }
+        match self.encoder {
+            Encoder::Gif(gif) => gif.finish(),
+            Encoder::Apng(apng) => apng.finish()
+        }
+    }
+
+    fn write(&mut self, frame: Pending, end: Duration) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::Gif(gif) => gif.frame(frame.rect, &frame.pixels, end),
+            Encoder::Apng(apng) => apng.frame(frame.rect, &frame.pixels, end)
+        }
+    }
+}
+
+/// The bounding rectangle (x, y, width, height) of the pixels that differ between the RGB images `a` and `b`
+fn changed_rect(a: &[u8], b: &[u8], size: (usize, usize)) -> Option<(usize, usize, usize, usize)> {
+    let stride = size.0 * 3;
+    let differs = |y: usize| a[y * stride..(y + 1) * stride] != b[y * stride..(y + 1) * stride];
+    let top = (0..size.1).find(|y| differs(*y))?;
+    let bottom = (top..size.1).rev().find(|y| differs(*y)).unwrap_or(top);
+
+    let (mut left, mut right) = (size.0, 0);
+    for y in top..=bottom {
+        let (a, b) = (&a[y * stride..(y + 1) * stride], &b[y * stride..(y + 1) * stride]);
+        if let Some(x) = (0..left).find(|x| a[x * 3..x * 3 + 3] != b[x * 3..x * 3 + 3]) {
+            left = x;
+        }
+        if let Some(x) = (right..size.0).rev().find(|x| a[x * 3..x * 3 + 3] != b[x * 3..x * 3 + 3]) {
+            right = x;
+        }
+    }
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+#[test]
+fn crops_frames_to_changes() {
+    let a = [0u8; 4 * 3 * 3];
+    let mut b = a;
+    // Change pixels (1, 0) and (2, 1) of the 4x3 image
+    b[3] = 1;
+    b[(4 + 2) * 3 + 2] = 1;
+    assert_eq!(changed_rect(&a, &a, (4, 3)), None);
+    assert_eq!(changed_rect(&a, &b, (4, 3)), Some((1, 0, 2, 2)));
+}
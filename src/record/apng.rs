@@ -0,0 +1,106 @@
+//!
+//! APNG writer for animations, in full 24-bit color.
+//!
+//! The amount of frames has to be known before the first frame, so frames are compressed
+//! and kept in memory until the animation is finished.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::time::Duration;
+use crate::codec::{deflate, png::{self, SIGNATURE}};
+
+pub(crate) struct ApngWriter<W: Write> {
+    writer: W,
+    size: (u32, u32),
+    /// The chunks of all frames written so far
+    chunks: Vec<u8>,
+    frames: u32,
+    /// The sequence number of the next `fcTL` or `fdAT` chunk
+    sequence: u32,
+    /// The time up to which frames have been written, in milliseconds
+    written: u64
+}
+
+impl<W: Write> ApngWriter<W> {
+    pub(crate) fn new(writer: W, size: (usize, usize)) -> io::Result<Self> {
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "Animation too large for APNG");
+        Ok(Self {
+            writer,
+            size: (u32::try_from(size.0).map_err(too_large)?, u32::try_from(size.1).map_err(too_large)?),
+            chunks: Vec::new(),
+            frames: 0,
+            sequence: 0,
+            written: 0
+        })
+    }
+
+    /// Write a frame of tightly packed RGB `pixels` covering `rect` (x, y, width, height),
+    /// shown until `end` (relative to the start of the animation).
+    /// The first frame has to cover the whole animation.
+    pub(crate) fn frame(&mut self, rect: (usize, usize, usize, usize), pixels: &[u8], end: Duration) -> io::Result<()> {
+        let end = end.as_millis() as u64;
+        let delay = end.saturating_sub(self.written).min(u16::MAX as u64);
+        self.written += delay;
+
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        for value in [rect.2, rect.3, rect.0, rect.1] {
+            control.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        control.extend_from_slice(&(delay as u16).to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        // Don't dispose, and replace the area of the frame
+        control.extend_from_slice(&[0, 0]);
+        png::write_chunk(&mut self.chunks, b"fcTL", &control)?;
+        self.sequence += 1;
+
+        let mut scanlines = Vec::with_capacity(rect.3 * (rect.2 * 3 + 1));
+        for row in pixels.chunks_exact(rect.2 * 3) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+        let data = deflate::compress(&scanlines);
+        if self.frames == 0 {
+            // The first frame is the default image as well
+            png::write_chunk(&mut self.chunks, b"IDAT", &data)?;
+        } else {
+            let mut chunk = Vec::with_capacity(data.len() + 4);
+            chunk.extend_from_slice(&self.sequence.to_be_bytes());
+            chunk.extend_from_slice(&data);
+            png::write_chunk(&mut self.chunks, b"fdAT", &chunk)?;
+            self.sequence += 1;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Write the animation, which fails without any frame, as an APNG needs a default image
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        if self.frames == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "APNG without any frame"));
+        }
+        self.writer.write_all(&SIGNATURE)?;
+        png::write_chunk(&mut self.writer, b"IHDR", &png::header(self.size.0 as usize, self.size.1 as usize)?)?;
+        let mut control = self.frames.to_be_bytes().to_vec();
+        // Loop forever
+        control.extend_from_slice(&0u32.to_be_bytes());
+        png::write_chunk(&mut self.writer, b"acTL", &control)?;
+        self.writer.write_all(&self.chunks)?;
+        png::write_chunk(&mut self.writer, b"IEND", &[])?;
+        self.writer.flush()
+    }
+}
+
+#[test]
+fn refuses_animations_without_frames() {
+    let mut output = Vec::new();
+    let error = ApngWriter::new(&mut output, (2, 2)).unwrap().finish().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(output.is_empty());
+
+    let mut apng = ApngWriter::new(&mut output, (1, 1)).unwrap();
+    apng.frame((0, 0, 1, 1), &[1, 2, 3], Duration::from_millis(100)).unwrap();
+    apng.finish().unwrap();
+    assert!(output.starts_with(&SIGNATURE));
+}
@@ -0,0 +1,218 @@
+//!
+//! GIF89a writer for animations, with a palette of up to 256 colors per frame.
+//!
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Bits per pixel index, the palette always has 256 entries
+const PALETTE_BITS: u8 = 8;
+const MAX_CODE: u16 = 4096;
+
+pub(crate) struct GifWriter<W: Write> {
+    writer: W,
+    /// The time up to which frames have been written, in centiseconds
+    written: u64
+}
+
+impl<W: Write> GifWriter<W> {
+    pub(crate) fn new(mut writer: W, size: (usize, usize)) -> io::Result<Self> {
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "Animation too large for GIF");
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&u16::try_from(size.0).map_err(too_large)?.to_le_bytes())?;
+        writer.write_all(&u16::try_from(size.1).map_err(too_large)?.to_le_bytes())?;
+        // No global color table, background color and aspect ratio unset
+        writer.write_all(&[0, 0, 0])?;
+        // Loop forever
+        writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(Self { writer, written: 0 })
+    }
+
+    /// Write a frame of tightly packed RGB `pixels` covering `rect` (x, y, width, height),
+    /// shown until `end` (relative to the start of the animation).
+    pub(crate) fn frame(&mut self, rect: (usize, usize, usize, usize), pixels: &[u8], end: Duration) -> io::Result<()> {
+        // Most viewers show frames of less than 2 centiseconds for 10 centiseconds instead.
+        // Delays are computed from the end of the previous frame, so rounding doesn't accumulate.
+        let end = (end.as_millis() as u64 + 5) / 10;
+        let delay = end.saturating_sub(self.written).clamp(2, u16::MAX as u64);
+        self.written += delay;
+
+        // Graphic control extension: don't dispose, no transparency
+        self.writer.write_all(&[0x21, 0xf9, 0x04, 0x04])?;
+        self.writer.write_all(&(delay as u16).to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        // Image descriptor with a local color table of 256 entries
+        self.writer.write_all(&[0x2c])?;
+        for value in [rect.0, rect.1, rect.2, rect.3] {
+            self.writer.write_all(&(value as u16).to_le_bytes())?;
+        }
+        self.writer.write_all(&[0x80 | (PALETTE_BITS - 1)])?;
+
+        let (palette, indices) = quantize(pixels);
+        let mut table = [0u8; 3 << PALETTE_BITS];
+        for (entry, color) in table.chunks_exact_mut(3).zip(&palette) {
+            entry.copy_from_slice(color);
+        }
+        self.writer.write_all(&table)?;
+
+        self.writer.write_all(&[PALETTE_BITS])?;
+        for block in lzw(&indices).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.writer.write_all(&[0x3b])?;
+        self.writer.flush()
+    }
+}
+
+/// Reduce RGB `pixels` to a palette of at most 256 colors, returning the palette and the index of each pixel.
+///
+/// Frames with at most 256 distinct colors, which is common for screen content, keep their exact colors.
+/// Others are reduced through median cut, on a histogram with 5 bits per channel.
+pub(crate) fn quantize(pixels: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut exact: HashMap<[u8; 3], u8> = HashMap::new();
+    for pixel in pixels.chunks_exact(3) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let next = exact.len();
+        if next > 256 {
+            break;
+        }
+        exact.entry(color).or_insert(next as u8);
+    }
+    if exact.len() <= 256 {
+        let mut palette = vec![[0; 3]; exact.len()];
+        exact.iter().for_each(|(color, index)| palette[*index as usize] = *color);
+        let indices = pixels.chunks_exact(3).map(|pixel| exact[&[pixel[0], pixel[1], pixel[2]]]).collect();
+        return (palette, indices);
+    }
+
+    let bucket = |pixel: &[u8]| (pixel[0] as usize >> 3) << 10 | (pixel[1] as usize >> 3) << 5 | pixel[2] as usize >> 3;
+    let mut histogram = vec![0u32; 1 << 15];
+    pixels.chunks_exact(3).for_each(|pixel| histogram[bucket(pixel)] += 1);
+    let color = |bucket: usize| [(bucket >> 10 & 31) as u8, (bucket >> 5 & 31) as u8, (bucket & 31) as u8];
+
+    // Split the box with the widest channel range at its median, until there are 256 boxes
+    let mut boxes = vec![(0..histogram.len()).filter(|bucket| histogram[*bucket] > 0).collect::<Vec<usize>>()];
+    while boxes.len() < 256 {
+        let range = |entries: &Vec<usize>| (0..3).map(|channel| {
+            let values = entries.iter().map(|bucket| color(*bucket)[channel]);
+            (values.clone().max().unwrap_or(0) - values.min().unwrap_or(0), channel)
+        }).max().unwrap_or((0, 0));
+        let Some((index, (_, channel))) = boxes.iter().map(range).enumerate()
+            .filter(|(index, (range, _))| *range > 0 && boxes[*index].len() > 1)
+            .max_by_key(|(_, (range, _))| *range) else { break };
+
+        let mut entries = boxes.swap_remove(index);
+        entries.sort_unstable_by_key(|bucket| color(*bucket)[channel]);
+        let total: u32 = entries.iter().map(|bucket| histogram[*bucket]).sum();
+        let (mut count, mut split) = (0, 1);
+        for (position, bucket) in entries.iter().enumerate() {
+            count += histogram[*bucket];
+            if count * 2 >= total {
+                split = (position + 1).clamp(1, entries.len() - 1);
+                break;
+            }
+        }
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    // Each box becomes the average of its colors, weighted by their pixel count
+    let mut lookup = vec![0u8; histogram.len()];
+    let palette = boxes.iter().enumerate().map(|(index, entries)| {
+        let mut sum = [0u64; 3];
+        let mut total = 0u64;
+        for bucket in entries {
+            let count = histogram[*bucket] as u64;
+            for (channel, value) in color(*bucket).iter().enumerate() {
+                sum[channel] += ((*value as u64) << 3 | 4) * count;
+            }
+            total += count;
+            lookup[*bucket] = index as u8;
+        }
+        sum.map(|sum| (sum / total.max(1)) as u8)
+    }).collect();
+    let indices = pixels.chunks_exact(3).map(|pixel| lookup[bucket(pixel)]).collect();
+    (palette, indices)
+}
+
+/// Compress palette `indices` with the variable length LZW of GIF, for a code size of 8 bits
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << PALETTE_BITS;
+    let end = clear + 1;
+    let mut out = Vec::with_capacity(indices.len() / 2);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        buffer |= (code as u32) << bits;
+        bits += size;
+        while bits >= 8 {
+            out.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::with_capacity(MAX_CODE as usize);
+    let mut size = PALETTE_BITS as u32 + 1;
+    let mut next = end + 1;
+    emit(clear, size, &mut out);
+
+    let Some((first, rest)) = indices.split_first() else {
+        emit(end, size, &mut out);
+        return out;
+    };
+    let mut prefix = *first as u16;
+    for index in rest {
+        if let Some(code) = table.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+        emit(prefix, size, &mut out);
+        // The decoder reads codes with one more bit as soon as the next code doesn't fit anymore
+        if next >= 1 << size && size < 12 {
+            size += 1;
+        }
+        if next < MAX_CODE {
+            table.insert((prefix, *index), next);
+            next += 1;
+        } else {
+            emit(clear, size, &mut out);
+            table.clear();
+            size = PALETTE_BITS as u32 + 1;
+            next = end + 1;
+        }
+        prefix = *index as u16;
+    }
+    emit(prefix, size, &mut out);
+    if next >= 1 << size && size < 12 {
+        size += 1;
+    }
+    emit(end, size, &mut out);
+    if bits > 0 {
+        out.push(buffer as u8);
+    }
+    out
+}
+
+#[test]
+fn quantizes_to_exact_palettes() {
+    let pixels = [255, 0, 0, 0, 0, 255, 255, 0, 0];
+    let (palette, indices) = quantize(&pixels);
+    assert_eq!(palette.len(), 2);
+    assert_eq!(palette[indices[0] as usize], [255, 0, 0]);
+    assert_eq!(palette[indices[1] as usize], [0, 0, 255]);
+    assert_eq!(indices[0], indices[2]);
+
+    // A gradient of 32768 colors is reduced to 256
+    let gradient = (0..1 << 15).flat_map(|value: u32| [(value >> 7) as u8, (value >> 2) as u8, (value << 3) as u8]).collect::<Vec<u8>>();
+    let (palette, _) = quantize(&gradient);
+    assert_eq!(palette.len(), 256);
+}
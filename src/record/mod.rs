@@ -0,0 +1,222 @@
+//!
+//! Recording an area of the display into a raw video file, or an animated clip.
+//!
+//! A [`Recorder`] captures frames at a fixed rate, converts them from BGRX to I420 (see [`yuv`](crate::yuv)) and writes them
+//! as an uncompressed [YUV4MPEG2](https://wiki.multimedia.cx/index.php/YUV4MPEG2) (`.y4m`) stream.
+//! Y4M is understood by virtually every video encoder (e.g. `ffmpeg -i capture.y4m`, `x264`),
+//! so recordings can be compressed offline, or piped into an encoder while recording.
+//!
+//! Short clips, e.g. to show how to reproduce a bug, can be recorded as animated GIF or APNG
+//! through [`Recorder::record_animation`], see [`Animation`].
+//!
+//! Frames are captured through `XGetImage`, through a [`SharedSession`](crate::shm::SharedSession)
+//! with the `shm` feature, which is considerably faster, or any other [`Capturer`].
+//!
+//! # Usage
+//! ```rust,no_run
+//! # use rxscreen::{Display, record::{AnimationFormat, Recorder}};
+//! # use std::time::Duration;
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let file = std::fs::File::create("./capture.y4m").unwrap();
+//!     let recording = Recorder::new(&display, (0, 0), (1280, 720))
+//!         .fps(30)
+//!         .duration(Duration::from_secs(10))
+//!         .record(std::io::BufWriter::new(file))
+//!         .unwrap();
+//!     println!("Recorded {} frames, {} dropped", recording.timestamps.len(), recording.dropped);
+//!
+//!     let file = std::fs::File::create("./clip.gif").unwrap();
+//!     Recorder::new(&display, (0, 0), (640, 480))
+//!         .fps(10)
+//!         .duration(Duration::from_secs(5))
+//!         .record_animation(std::io::BufWriter::new(file), AnimationFormat::Gif)
+//!         .unwrap();
+//! }
+//! ```
+//!
+
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::{Display, Image, capture::{CaptureError, Capturer, Region}, clock::FrameClock, yuv::{self, Conversion, Range, YuvFormat}};
+
+mod animation;
+mod apng;
+mod gif;
+
+pub use animation::{Animation, AnimationFormat};
+
+#[derive(Debug)]
+pub enum RecordError {
+    CaptureFailed,
+    Io(io::Error)
+}
+
+impl From<io::Error> for RecordError {
+    fn from(error: io::Error) -> Self {
+        RecordError::Io(error)
+    }
+}
+
+/// The outcome of [`Recorder::record`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// When each captured frame was taken, relative to the start of the recording
+    pub timestamps: Vec<Duration>,
+    /// The amount of frames that couldn't be captured in time.
+    /// In Y4M recordings their slots are filled by repeating the previous frame, so the video keeps its length.
+    pub dropped: usize,
+    /// The length of the recording
+    pub length: Duration
+}
+
+/// Records an area of the display into a Y4M stream, see the [module documentation](self).
+pub struct Recorder<C: Capturer> {
+    capturer: C,
+    fps: u32,
+    conversion: Conversion,
+    duration: Option<Duration>,
+    stop: Option<Arc<AtomicBool>>
+}
+
+impl<'a> Recorder<Region<'a>> {
+    /// Record the area of `size` (width, height) at `offset` (x, y) through `XGetImage`
+    pub fn new(display: &'a Display, offset: (u32, u32), size: (u32, u32)) -> Self {
+        Self::with_capturer(display.region(offset, size))
+    }
+    /// Record `monitor` through `XGetImage`, at its refresh rate, or 30 fps if RandR doesn't report one
+    #[cfg(feature = "xrandr")]
+    pub fn monitor(display: &'a Display, monitor: &crate::monitor::Monitor) -> Self {
+        let fps = display.screen_resources()
+            .and_then(|resources| resources.refresh_rate(monitor))
+            .map_or(30, |rate| rate.round() as u32);
+        Self::new(display, (monitor.x() as u32, monitor.y() as u32), (monitor.width() as u32, monitor.height() as u32)).fps(fps)
+    }
+}
+
+#[cfg(feature = "shm")]
+impl<'s, 'a> Recorder<&'s mut crate::shm::SharedSession<'a>> {
+    /// Record the area of a shared session
+    pub fn shared(session: &'s mut crate::shm::SharedSession<'a>) -> Self {
+        Self::with_capturer(session)
+    }
+}
+
+impl<C: Capturer> Recorder<C> {
+    /// Record the frames of `capturer`.
+    /// A capturer running out of frames ([`CaptureError::Exhausted`]) ends the recording.
+    pub fn with_capturer(capturer: C) -> Self {
+        Self {
+            capturer,
+            fps: 30,
+            conversion: Conversion::default(),
+            duration: None,
+            stop: None
+        }
+    }
+    /// Set the frame rate of the recording, 30 by default
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps.max(1);
+        self
+    }
+    /// Set the matrix and range of the YUV conversion, BT.601 in limited range by default.
+    /// Y4M only stores the range, the matrix has to be passed to the encoder, e.g. `x264 --colormatrix bt709`.
+    pub fn conversion(mut self, conversion: Conversion) -> Self {
+        self.conversion = conversion;
+        self
+    }
+    /// Stop the recording after `duration`
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+    /// Stop the recording once `stop` is set, e.g. from another thread or a signal handler
+    pub fn stop_signal(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Record into `writer` until the duration elapsed or the stop signal got set.
+    /// Without either, the recording only ends once a capture or a write fails.
+    pub fn record(&mut self, mut writer: impl Write) -> Result<Recording, RecordError> {
+        let (width, height) = self.capturer.geometry().size;
+        let conversion = self.conversion;
+        let range = match conversion.range {
+            Range::Limited => "LIMITED",
+            Range::Full => "FULL"
+        };
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg XCOLORRANGE={}", width, height, self.fps, range)?;
+
+        let mut frame = vec![0; YuvFormat::I420.frame_size(width as usize, height as usize)];
+        let recording = self.run(|image, _, dropped| {
+            for _ in 0..dropped {
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&frame)?;
+            }
+            yuv::convert(&image.pixels(), YuvFormat::I420, conversion, &mut frame)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Capture doesn't have the size of the recording"))?;
+            writer.write_all(b"FRAME\n")?;
+            writer.write_all(&frame)
+        })?;
+        writer.flush()?;
+        Ok(recording)
+    }
+
+    /// Record an animated clip into `writer`, until the duration elapsed or the stop signal got set.
+    ///
+    /// The delay of each frame is taken from the actual capture timestamps, so the clip plays back
+    /// in real time even if frames got dropped.
+    pub fn record_animation(&mut self, writer: impl Write, format: AnimationFormat) -> Result<Recording, RecordError> {
+        let mut animation = Animation::new(writer, format, self.capturer.geometry().size)?;
+        let recording = self.run(|image, timestamp, _| animation.push(&image.pixels(), timestamp))?;
+        animation.finish(recording.length)?;
+        Ok(recording)
+    }
+
+    /// Capture frames at the configured rate until the recording is over, passing each frame with its
+    /// timestamp and the amount of frames dropped right before it to `frame`.
+    fn run(&mut self, mut frame: impl FnMut(&Image, Duration, usize) -> io::Result<()>) -> Result<Recording, RecordError> {
+        let mut clock = FrameClock::new(self.fps);
+        let mut recording = Recording { timestamps: vec![], dropped: 0, length: Duration::ZERO };
+
+        loop {
+            let tick = clock.tick();
+            if self.duration.is_some_and(|duration| tick.timestamp >= duration)
+                || self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                break;
+            }
+            // Slots skipped before the first frame have nothing to repeat
+            let dropped = if tick.index > tick.dropped { tick.dropped as usize } else { 0 };
+            let image = match self.capturer.capture() {
+                Ok(image) => image,
+                Err(CaptureError::Exhausted) => break,
+                Err(CaptureError::CaptureFailed) => return Err(RecordError::CaptureFailed)
+            };
+            frame(&image, tick.timestamp, dropped)?;
+            drop(image);
+            clock.done(&tick);
+            recording.timestamps.push(tick.timestamp);
+            recording.dropped += dropped;
+        }
+        let elapsed = clock.elapsed();
+        recording.length = self.duration.map_or(elapsed, |duration| duration.min(elapsed));
+        Ok(recording)
+    }
+}
+
+#[test]
+fn records_until_the_capturer_is_exhausted() {
+    let display = crate::mock::MockDisplay::new(2, 2)
+        .frame(&[255; 12])
+        .frame(&[0; 12]);
+    let mut output = Vec::new();
+    let recording = Recorder::with_capturer(&display).fps(10).record(&mut output).unwrap();
+    assert_eq!((recording.timestamps.len(), recording.dropped), (2, 0));
+
+    let header = b"YUV4MPEG2 W2 H2 F10:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n";
+    assert!(output.starts_with(header));
+    // Both frames, with 4 luma and 2 chroma samples each
+    assert_eq!(output.len(), header.len() + 2 * (6 + 6));
+    assert_eq!(&output[header.len()..header.len() + 10], b"FRAME\n\xeb\xeb\xeb\xeb");
+}
@@ -0,0 +1,439 @@
+//!
+//! Encoding captures into image files.
+//!
+//! This module uses the `image` crate to encode an [`Image`] into one of the formats of [`Format`].
+//! The encoder is configured through [`SaveOptions`], and can write to any [`std::io::Write`],
+//! be it a file, a buffer in memory or e.g. the body of a HTTP request.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, save::{Format, SaveOptions}};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let capture = display.capture().unwrap();
+//!     let mut body: Vec<u8> = vec![];
+//!     capture.encode(&mut body, &SaveOptions::new(Format::Jpeg).quality(85))
+//!         .unwrap();
+//!     // `body` now contains the JPEG encoded capture
+//! }
+//! ```
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::{Display, Image};
+use crate::codec::Encoding;
+use crate::ffi::constants::LSBFirst;
+use image::{DynamicImage, RgbImage, RgbaImage};
+
+/// The error of converting an [`Image`] into an image of the `image` crate
+#[derive(PartialEq, Debug)]
+pub enum ConversionError {
+    /// Only 32-bit little endian TrueColor pixels (BGRX) are supported
+    UnsupportedFormat { bits_per_pixel: i32, depth: i32 }
+}
+
+/// The file formats an [`Image`] can be encoded to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    /// Binary PPM
+    Pnm,
+    /// Windows icon, limited to images of at most 256x256 pixels
+    Ico
+}
+
+impl Format {
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "bmp" => Some(Format::Bmp),
+            "tga" => Some(Format::Tga),
+            "pnm" | "ppm" => Some(Format::Pnm),
+            "ico" => Some(Format::Ico),
+            _ => None
+        }
+    }
+}
+
+/// Compression level of the PNG encoder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PngCompression {
+    Default,
+    Fast,
+    Best,
+    Huffman,
+    Rle
+}
+
+/// Filter applied to the scanlines by the PNG encoder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth
+}
+
+/// The format and encoder settings used by [`Image::encode`] and [`Image::save_with`].
+///
+/// Settings that don't apply to the chosen format are ignored.
+/// ```rust
+/// # use rxscreen::save::{Format, PngCompression, PngFilter, SaveOptions};
+/// let png = SaveOptions::new(Format::Png)
+///     .compression(PngCompression::Best)
+///     .filter(PngFilter::Paeth);
+/// let jpeg = SaveOptions::new(Format::Jpeg).quality(85);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveOptions {
+    format: Format,
+    compression: PngCompression,
+    filter: PngFilter,
+    quality: u8
+}
+
+impl SaveOptions {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            compression: PngCompression::Fast,
+            filter: PngFilter::Sub,
+            quality: 75
+        }
+    }
+    /// Set the compression level (PNG)
+    pub fn compression(mut self, compression: PngCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+    /// Set the scanline filter (PNG)
+    pub fn filter(mut self, filter: PngFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+    /// Set the quality from 1 to 100 (JPEG)
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+    pub fn format(&self) -> Format {
+        self.format
+    }
+}
+
+impl Default for SaveOptions {
+    /// PNG with the default settings of the `image` crate
+    fn default() -> Self {
+        Self::new(Format::Png)
+    }
+}
+
+/// Turn errors of the `image` crate into `io::Error`s, keeping the original error as the cause.
+fn io_error(error: image::ImageError) -> io::Error {
+    match error {
+        image::ImageError::IoError(error) => error,
+        error => io::Error::other(error)
+    }
+}
+
+impl Image {
+    /// Restructure the Bgr8 data into tightly packed Rgb8
+    /// Whether the pixels are 32-bit little endian TrueColor, i.e. BGRX, as the conversions assume
+    fn check_format(&self) -> Result<(), ConversionError> {
+        let image = unsafe { &*self.raw };
+        if image.bits_per_pixel == 32 && image.byte_order == LSBFirst as i32 && (image.depth == 24 || image.depth == 32)
+            && (image.red_mask, image.green_mask, image.blue_mask) == (0xff0000, 0xff00, 0xff) {
+            return Ok(());
+        }
+        Err(ConversionError::UnsupportedFormat { bits_per_pixel: image.bits_per_pixel, depth: image.depth })
+    }
+    fn to_rgb_buffer(&self) -> Vec<u8> {
+        let pixels = self.pixels();
+        let mut buffer = Vec::with_capacity(pixels.width() * pixels.height() * 3);
+        for y in 0..pixels.height() {
+            pixels.rgb_row(y).for_each(|rgb| buffer.extend_from_slice(&rgb));
+        }
+        buffer
+    }
+
+    /// Encode the image with `options` and write it to `writer`
+    ///
+    /// # Errors
+    ///
+    /// Errors of the encoder are returned with the original error of the `image` crate as their cause,
+    /// I/O errors of `writer` are passed through unchanged. Images in other formats than BGRX fail with `InvalidData`.
+    pub fn encode(&self, mut writer: impl Write, options: &SaveOptions) -> io::Result<()> {
+        use image::{ColorType, codecs::{png, jpeg, bmp, tga, pnm, ico}};
+        self.check_format().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)))?;
+        let buffer = self.to_rgb_buffer();
+        let (width, height) = (self.width() as u32, self.height() as u32);
+
+        let result = match options.format {
+            Format::Png => {
+                let compression = match options.compression {
+                    PngCompression::Default => png::CompressionType::Default,
+                    PngCompression::Fast => png::CompressionType::Fast,
+                    PngCompression::Best => png::CompressionType::Best,
+                    PngCompression::Huffman => png::CompressionType::Huffman,
+                    PngCompression::Rle => png::CompressionType::Rle
+                };
+                let filter = match options.filter {
+                    PngFilter::NoFilter => png::FilterType::NoFilter,
+                    PngFilter::Sub => png::FilterType::Sub,
+                    PngFilter::Up => png::FilterType::Up,
+                    PngFilter::Avg => png::FilterType::Avg,
+                    PngFilter::Paeth => png::FilterType::Paeth
+                };
+                png::PngEncoder::new_with_quality(&mut writer, compression, filter).encode(&buffer, width, height, ColorType::Rgb8)
+            },
+            Format::Jpeg => jpeg::JpegEncoder::new_with_quality(&mut writer, options.quality).encode(&buffer, width, height, ColorType::Rgb8),
+            Format::Bmp => bmp::BmpEncoder::new(&mut writer).encode(&buffer, width, height, ColorType::Rgb8),
+            Format::Tga => tga::TgaEncoder::new(&mut writer).encode(&buffer, width, height, ColorType::Rgb8),
+            Format::Pnm => pnm::PnmEncoder::new(&mut writer)
+                .with_subtype(pnm::PNMSubtype::Pixmap(pnm::SampleEncoding::Binary))
+                .encode(&buffer[..], width, height, ColorType::Rgb8),
+            Format::Ico => ico::IcoEncoder::new(&mut writer).encode(&buffer, width, height, ColorType::Rgb8)
+        };
+        result.map_err(io_error)?;
+        writer.flush()
+    }
+
+    /// Saves the image to `file`, encoded with `options`
+    ///
+    /// ```rust
+    /// # use rxscreen::{Display, save::{Format, SaveOptions}};
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let screenshot = display.capture().unwrap();
+    ///     screenshot.save_with("./screenshot.jpg", &SaveOptions::new(Format::Jpeg).quality(90));
+    /// }
+    /// ```
+    pub fn save_with(&self, file: impl AsRef<Path>, options: &SaveOptions) -> io::Result<()> {
+        let file = std::fs::File::create(file)?;
+        self.encode(io::BufWriter::new(file), options)
+    }
+
+    /// Saves the image to file
+    ///
+    /// The format is inferred from the extension of `file`, see [`Format::from_path`].
+    /// Extensions of the built-in encoders, see [`Encoding::from_path`](crate::codec::Encoding::from_path),
+    /// and any other filetype that the `image`-crate supports can be used as well.
+    ///
+    /// ```rust
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let screenshot = display.capture();
+    ///     screenshot.unwrap().save_as("./screenshot.png");
+    /// }
+    /// ```
+    ///
+    /// # Warning
+    ///
+    /// **Without** configuring any opt-level (debug build) this function may take upwards of 10 seconds for one screenshot
+    /// (measured on debug build, 5760x1080 capture, ryzen 7 2700x, gtx 1050 ti)
+    ///
+    /// **With opt-level 3** it takes 0.2 seconds for a capture of the same size.
+    pub fn save_as(&self, file: impl Into<std::path::PathBuf>) -> io::Result<()> {
+        let file = file.into();
+        if let Some(format) = Format::from_path(&file) {
+            return self.save_with(&file, &SaveOptions::new(format));
+        }
+        match Encoding::from_path(&file) {
+            Some(encoding) => self.write_as(io::BufWriter::new(std::fs::File::create(file)?), encoding),
+            None => {
+                let buffer = self.to_rgb_buffer();
+                image::save_buffer(&file, &buffer, self.width() as u32, self.height() as u32, image::ColorType::Rgb8)
+                    .map_err(io_error)
+            }
+        }
+    }
+
+    /// Saves the raw capture to PNG in memory
+    ///
+    /// ```rust
+    /// # use rxscreen::{Display, Image};
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let screenshot = display.capture().unwrap();
+    ///     let data = screenshot.save_to_memory();
+    ///     // `data` now contains the encoded PNG file
+    /// }
+    /// ```
+    pub fn save_to_memory(self) -> io::Result<Vec<u8>> {
+        let mut png_data: Vec<u8> = vec![];
+        self.encode(&mut png_data, &SaveOptions::default())?;
+        Ok(png_data)
+    }
+}
+
+impl TryFrom<&Image> for RgbImage {
+    type Error = ConversionError;
+    fn try_from(image: &Image) -> Result<Self, ConversionError> {
+        image.check_format()?;
+        Ok(RgbImage::from_raw(image.width() as u32, image.height() as u32, image.to_rgb_buffer())
+            .expect("buffer has the size of the image"))
+    }
+}
+
+impl TryFrom<&Image> for RgbaImage {
+    type Error = ConversionError;
+    /// The alpha channel of the result is opaque, as X11 visuals carry none
+    fn try_from(image: &Image) -> Result<Self, ConversionError> {
+        image.check_format()?;
+        let pixels = image.pixels();
+        let mut buffer = Vec::with_capacity(pixels.width() * pixels.height() * 4);
+        for y in 0..pixels.height() {
+            pixels.rgb_row(y).for_each(|[r, g, b]| buffer.extend_from_slice(&[r, g, b, 255]));
+        }
+        Ok(RgbaImage::from_raw(image.width() as u32, image.height() as u32, buffer)
+            .expect("buffer has the size of the image"))
+    }
+}
+
+impl TryFrom<&Image> for DynamicImage {
+    type Error = ConversionError;
+    fn try_from(image: &Image) -> Result<Self, ConversionError> {
+        RgbImage::try_from(image).map(DynamicImage::ImageRgb8)
+    }
+}
+
+impl Image {
+    /// Create an image in the format of the X server from an image of the `image` crate,
+    /// e.g. to draw it onto a window. Transparency is dropped.
+    pub fn from_dynamic_image(display: &Display, image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageRgb8(rgb) => Image::from_rgb_image(display, rgb),
+            image => Image::from_rgba_image(display, &image.to_rgba8())
+        }
+    }
+    /// Decode an image file into an image in the format of `display`
+    ///
+    /// Any format the `image` crate or the built-in decoders can read is supported,
+    /// it is detected from the contents of `data`.
+    pub fn decode(display: &Display, data: &[u8]) -> io::Result<Image> {
+        if image::guess_format(data).is_err() {
+            if let Some(encoding) = Encoding::detect(data) {
+                return Image::decode_as(display, data, encoding);
+            }
+        }
+        let image = image::load_from_memory(data).map_err(io_error)?;
+        Ok(Image::from_dynamic_image(display, &image))
+    }
+    /// Create an image in the format of the X server from a `image::RgbImage`
+    pub fn from_rgb_image(display: &Display, image: &RgbImage) -> Self {
+        Image::from_packed(display, image.width(), image.height(), 3, image.as_raw())
+    }
+    /// Create an image in the format of the X server from a `image::RgbaImage`, dropping the alpha channel
+    pub fn from_rgba_image(display: &Display, image: &RgbaImage) -> Self {
+        Image::from_packed(display, image.width(), image.height(), 4, image.as_raw())
+    }
+}
+
+#[test]
+fn infers_formats_from_extensions() {
+    assert_eq!(Format::from_path("capture.PNG"), Some(Format::Png));
+    assert_eq!(Format::from_path("/tmp/capture.jpeg"), Some(Format::Jpeg));
+    assert_eq!(Format::from_path("capture.ppm"), Some(Format::Pnm));
+    assert_eq!(Format::from_path("capture.webp"), None);
+    assert_eq!(Format::from_path("capture"), None);
+}
+
+#[test]
+fn saves_formats_of_the_builtin_encoders() {
+    let image = Image::detached(4, 3);
+    let directory = std::env::temp_dir();
+    for (name, signature) in [("capture.qoi", &b"qoif"[..]), ("capture.pam", &b"P7"[..])] {
+        let path = directory.join(format!("rxscreen-{}-{}", std::process::id(), name));
+        image.save_as(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data.starts_with(signature), "{} starts with {:?}", name, &data[..4]);
+        let decoded = Encoding::detect(&data).unwrap().decode(&data).unwrap();
+        assert_eq!((decoded.width, decoded.height), (4, 3));
+    }
+}
+
+#[cfg(test)]
+fn gradient(width: u32, height: u32) -> Image {
+    let mut image = Image::detached(width, height);
+    for (i, bgrx) in unsafe { image.as_bytes_mut() }.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        bgrx.copy_from_slice(&[(x * 7) as u8, (y * 5) as u8, (x * y) as u8, 0]);
+    }
+    image
+}
+
+#[test]
+fn encodes_into_memory() {
+    let image = gradient(32, 16);
+    let signatures: [(Format, &[u8]); 5] = [
+        (Format::Png, &[0x89, b'P', b'N', b'G']),
+        (Format::Jpeg, &[0xff, 0xd8, 0xff]),
+        (Format::Bmp, b"BM"),
+        (Format::Pnm, b"P6"),
+        (Format::Ico, &[0, 0, 1, 0])
+    ];
+    for (format, signature) in signatures {
+        let mut data = vec![];
+        image.encode(&mut data, &SaveOptions::new(format)).unwrap();
+        assert!(data.starts_with(signature), "{:?} starts with {:?}", format, &data[..4]);
+    }
+}
+
+#[test]
+fn applies_the_jpeg_quality() {
+    let image = gradient(32, 16);
+    let encode = |quality| {
+        let mut data = vec![];
+        image.encode(&mut data, &SaveOptions::new(Format::Jpeg).quality(quality)).unwrap();
+        data
+    };
+    let (low, high) = (encode(10), encode(95));
+    assert_ne!(low, high);
+    assert!(low.len() < high.len());
+}
+
+#[test]
+fn returns_encoder_errors_with_their_cause() {
+    // JPEGs can be at most 65535 pixels wide
+    let error = Image::detached(65536, 1).encode(vec![], &SaveOptions::new(Format::Jpeg)).unwrap_err();
+    let cause = error.get_ref().expect("the error of the image crate is kept as the cause");
+    assert!(matches!(cause.downcast_ref::<image::ImageError>(), Some(image::ImageError::Parameter(_))));
+
+    struct Broken;
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let error = gradient(4, 4).encode(Broken, &SaveOptions::new(Format::Bmp)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn converts_bgrx_images_only() {
+    let image = Image::detached(2, 1);
+    assert_eq!(RgbImage::try_from(&image).unwrap().dimensions(), (2, 1));
+    assert_eq!(RgbaImage::try_from(&image).unwrap().get_pixel(1, 0).0, [0, 0, 0, 255]);
+    assert!(DynamicImage::try_from(&image).is_ok());
+
+    // A 16-bit visual
+    unsafe {
+        let raw = image.raw as *mut crate::ffi::XImage;
+        (*raw).bits_per_pixel = 16;
+        (*raw).depth = 16;
+    }
+    let unsupported = ConversionError::UnsupportedFormat { bits_per_pixel: 16, depth: 16 };
+    assert_eq!(RgbImage::try_from(&image).err(), Some(unsupported));
+    assert!(DynamicImage::try_from(&image).is_err());
+    assert_eq!(image.encode(Vec::new(), &SaveOptions::default()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
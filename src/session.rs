@@ -0,0 +1,149 @@
+//!
+//! Capture sessions which work with local and remote X servers alike.
+//!
+//! A [`CaptureSession`] captures through MIT-SHM, if the `shm` feature is enabled and the server
+//! can attach shared segments, which requires it to run on the same machine. Otherwise, e.g. over
+//! `ssh -X`, it falls back to `XGetImage` into a buffer that is reused for every capture.
+//! Both backends are used the same way, [`CaptureSession::backend`] reports which one is in use.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, session::{Backend, CaptureSession}};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let mut session = CaptureSession::full(&display);
+//!     if session.backend() == Backend::GetImage {
+//!         println!("MIT-SHM unavailable, capturing through XGetImage");
+//!     }
+//!     let capture = session.capture().unwrap();
+//! }
+//! ```
+//!
+
+use crate::{Display, Image, ffi::{XGetSubImage, constants::{AllPlanes, ZPixmap}}};
+
+/// The way a [`CaptureSession`] captures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// MIT-SHM, the server writes captures straight into shared memory
+    Shm,
+    /// `XGetImage`, captures are sent through the connection
+    GetImage
+}
+
+enum Inner<'a> {
+    #[cfg(feature = "shm")]
+    Shm(crate::shm::SharedSession<'a>),
+    GetImage { display: &'a Display, image: Image }
+}
+
+/// Captures an area of a display through MIT-SHM or `XGetImage`, see the [module documentation](self).
+pub struct CaptureSession<'a> {
+    inner: Inner<'a>,
+    offset: (u32, u32),
+    area: (u32, u32)
+}
+
+impl<'a> CaptureSession<'a> {
+    /// Capture `area` (width, height) at `offset` (x, y), through MIT-SHM if the server supports it
+    pub fn new(display: &'a Display, offset: (u32, u32), area: (u32, u32)) -> Self {
+        #[cfg(feature = "shm")]
+        if let Ok(session) = display.shm().area(offset, area).build() {
+            return Self { inner: Inner::Shm(session), offset, area };
+        }
+        Self::get_image(display, offset, area)
+    }
+    /// Capture the whole display, through MIT-SHM if the server supports it
+    pub fn full(display: &'a Display) -> Self {
+        Self::new(display, (0, 0), (display.width, display.height))
+    }
+    /// Capture `area` (width, height) at `offset` (x, y) through `XGetImage`, even if MIT-SHM is available
+    pub fn get_image(display: &'a Display, offset: (u32, u32), area: (u32, u32)) -> Self {
+        Self { inner: Inner::GetImage { display, image: Image::empty(display, area.0, area.1) }, offset, area }
+    }
+
+    /// Capture the area. The returned image is overwritten by the next capture.
+    ///
+    /// # Errors
+    ///
+    /// This function fails silently if the capture failed for some reason, e.g. as the area reaches past the screen.
+    #[allow(clippy::result_unit_err)]
+    pub fn capture(&mut self) -> Result<&Image, ()> {
+        match &mut self.inner {
+            #[cfg(feature = "shm")]
+            Inner::Shm(session) => session.capture_at(self.offset),
+            Inner::GetImage { display, image } => {
+                if !display.contains(self.offset, self.area) {
+                    return Err(());
+                }
+                let captured = unsafe {
+                    XGetSubImage(display.connection, display.window, self.offset.0 as i32, self.offset.1 as i32,
+                        self.area.0, self.area.1, AllPlanes, ZPixmap as i32, image.raw, 0, 0)
+                };
+                if captured.is_null() {
+                    Err(())
+                }else{
+                    Ok(image)
+                }
+            }
+        }
+    }
+
+    /// The backend the session captures through
+    pub fn backend(&self) -> Backend {
+        match self.inner {
+            #[cfg(feature = "shm")]
+            Inner::Shm(_) => Backend::Shm,
+            Inner::GetImage { .. } => Backend::GetImage
+        }
+    }
+    /// Capture at `offset` (x, y) from now on
+    pub fn set_offset(&mut self, offset: (u32, u32)) {
+        self.offset = offset;
+    }
+    pub fn offset(&self) -> (u32, u32) {
+        self.offset
+    }
+    pub fn area(&self) -> (u32, u32) {
+        self.area
+    }
+}
+
+#[test]
+fn captures_through_get_image_on_request() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let session = CaptureSession::get_image(&display, (0, 0), (100, 100));
+    assert_eq!(session.backend(), Backend::GetImage);
+}
+
+#[test]
+fn refuses_to_capture_past_the_screen() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut session = CaptureSession::get_image(&display, (300, 0), (100, 100));
+    assert!(session.capture().is_err());
+    session.set_offset((220, 140));
+    assert!(session.capture().is_ok());
+}
+
+#[test]
+fn backends_capture_identical_pixels() {
+    use crate::ffi::{XCreateGC, XFreeGC, XPutImage, XSync};
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    // Paint a gradient onto the root window, so the captures have something to differ in
+    let gradient: Vec<u8> = (0..240u32).flat_map(|y| (0..320u32).flat_map(move |x| [x as u8, y as u8, (x ^ y) as u8])).collect();
+    let image = Image::from_packed(&display, 320, 240, 3, &gradient);
+    unsafe {
+        let gc = XCreateGC(display.connection, display.window, 0, std::ptr::null());
+        XPutImage(display.connection, display.window, gc, image.raw, 0, 0, 0, 0, 320, 240);
+        XFreeGC(display.connection, gc);
+        XSync(display.connection, 0);
+    }
+
+    let mut preferred = CaptureSession::new(&display, (10, 20), (100, 50));
+    let mut fallback = CaptureSession::get_image(&display, (10, 20), (100, 50));
+    let (preferred, fallback) = (preferred.capture().unwrap().pixels(), fallback.capture().unwrap().pixels());
+    assert_eq!((preferred.width(), preferred.height()), (100, 50));
+    for y in 0..50 {
+        assert!(preferred.rgb_row(y).eq(fallback.rgb_row(y)), "row {} differs", y);
+    }
+    assert!(preferred.rgb_row(0).eq((10..110u32).map(|x| [x as u8, 20, (x ^ 20) as u8])));
+}
@@ -27,61 +27,594 @@
 //!
 //! ```
 //!
+//! # Threads
+//!
+//! A [`SharedSession`] either borrows its [`Display`], or owns it through an [`Arc`], see [`ShmBuilder::owned`].
+//! An [`OwnedSession`] isn't tied to any lifetime, so it can be stored next to its display or moved to a capture thread.
+//!
+//! Sessions are `Send`, but not `Sync`: one thread at a time captures through a session, while the
+//! [`FrameGuard`]s it handed out can be processed on any thread. The display itself may keep being used
+//! by other threads meanwhile, as `Display::new` initializes Xlib for threads through `XInitThreads`.
+//!
+//! ```rust,no_run
+//! # use rxscreen::{Display, shm::ShmBuilder};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let mut session = ShmBuilder::owned(display).full().build().unwrap();
+//!     let capturer = std::thread::spawn(move || {
+//!         let capture = session.capture().unwrap();
+//!         capture.pixels().width()
+//!     });
+//!     capturer.join().unwrap();
+//! }
+//! ```
 //!
 
 
-use core::ffi::{c_char, c_void};
+use core::ffi::{c_char, c_int, c_void};
 use crate::{Display, Image, ffi::{*, constants::*}};
+use std::cell::Cell;
+use std::ops::Deref;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(PartialEq, Debug)]
 pub enum ShmError {
     ExtensionNotAvailable,
     ShmInitFailed,
-    ShmAttachFailed
+    ShmAttachFailed,
+    /// `XShmGetImage` failed
+    CaptureFailed,
+    /// Every buffer of the session is still held by a [`FrameGuard`]
+    NoFreeBuffer,
+    /// The captured area reaches past the screen
+    OutOfBounds
+}
+
+
+/// The memory behind a [`Segment`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Memory {
+    /// A `memfd` mapped into the process and passed to the server through MIT-SHM 1.2
+    Memfd,
+    /// A System V segment, removed right after the server attached it
+    SysV
+}
+
+/// A shared memory segment attached to the X server, with an `XImage` on top of it.
+///
+/// Segments are backed by a `memfd`, if the server supports MIT-SHM 1.2 and XCB is available,
+/// and a System V segment otherwise. Either way, the memory is released by the kernel once the
+/// process and the server are done with it, even if the process crashes before the segment is dropped.
+pub(crate) struct Segment {
+    connection: XDisplay,
+    shminfo: Pin<Box<XShmSegmentInfo>>,
+    memory: Memory,
+    /// The size of the segment in bytes
+    capacity: usize,
+    pub(crate) image: Image
+}
+
+impl Segment {
+    /// Allocate a segment for an image of `area` (width, height) in the default visual and depth,
+    /// and attach it to the X server.
+    pub(crate) fn create(display: &Display, area: (u32, u32)) -> Result<Self, ShmError> {
+        #[cfg(feature = "dlopen")]
+        if !crate::ffi::library::XEXT.available() {
+            return Err(ShmError::ExtensionNotAvailable);
+        }
+        unsafe {
+            if !XShmQueryExtension(display.connection) {
+                return Err(ShmError::ExtensionNotAvailable);
+            }
+            let vis = XDefaultVisual(display.connection, 0);
+            let depth = XDefaultDepth(display.connection, 0) as u32;
+
+            let mut shminfo = Box::pin(XShmSegmentInfo { shmseg: 0, shmid: 0, shmaddr: std::ptr::null(), read_only: 0});
+
+            let ximg = XShmCreateImage(display.connection, vis, depth, ZPixmap as i32, std::ptr::null(), shminfo.as_ref().get_ref(), area.0, area.1) as *mut XImage;
+            if ximg.is_null() {
+                return Err(ShmError::ShmInitFailed);
+            }
+            let image = Image { raw: ximg, detached: false };
+
+            let capacity = ((*ximg).bytes_per_line * (*ximg).height) as usize;
+            let memory = if Self::attach_memfd(display, &mut shminfo, capacity) {
+                Memory::Memfd
+            }else{
+                Self::attach_sysv(display, &mut shminfo, capacity)?;
+                Memory::SysV
+            };
+            (*ximg).data = shminfo.shmaddr as *mut c_char;
+            Ok(Segment {
+                connection: display.connection,
+                shminfo,
+                memory,
+                capacity,
+                image
+            })
+        }
+    }
+
+    /// Create a `memfd` of `capacity` bytes, map it and hand it to the server.
+    /// Returns `false` if the server or XCB lack support for it, or any step failed.
+    unsafe fn attach_memfd(display: &Display, shminfo: &mut XShmSegmentInfo, capacity: usize) -> bool {
+        let Some(xcb) = XcbShm::get() else { return false };
+        // Descriptors can only be passed over unix sockets, not to remote servers
+        if !is_local(display) {
+            return false;
+        }
+        let (mut major, mut minor, mut pixmaps) = (0, 0, 0);
+        if XShmQueryVersion(display.connection, &mut major, &mut minor, &mut pixmaps) == 0 || (major, minor) < (1, 2) {
+            return false;
+        }
+        let Some((fd, address)) = map_memfd(capacity) else { return false };
+
+        let connection = (xcb.get_connection)(display.connection);
+        let shmseg = (xcb.generate_id)(connection);
+        // XCB closes the descriptor once it has been sent, the mapping keeps the memory alive
+        let error = (xcb.request_check)(connection, (xcb.attach_fd_checked)(connection, shmseg, fd, 0));
+        if !error.is_null() {
+            libc::free(error);
+            libc::munmap(address, capacity);
+            return false;
+        }
+        shminfo.shmseg = shmseg as ShmSeg;
+        shminfo.shmid = -1;
+        shminfo.shmaddr = address as *const c_char;
+        true
+    }
+
+    /// Create a System V segment of `capacity` bytes, attach it to the process and the server.
+    unsafe fn attach_sysv(display: &Display, shminfo: &mut XShmSegmentInfo, capacity: usize) -> Result<(), ShmError> {
+        use libc::{shmget, shmat, shmctl, shmid_ds, IPC_PRIVATE, IPC_CREAT, IPC_RMID};
+        shminfo.shmid = shmget(IPC_PRIVATE, capacity, IPC_CREAT|0o600);
+        if shminfo.shmid == -1 {
+            return Err(ShmError::ShmInitFailed);
+        }
+
+        let memory_addr = shmat(shminfo.shmid, std::ptr::null(), 0) as *mut c_char;
+        if memory_addr as isize == -1 {
+            shmctl(shminfo.shmid, IPC_RMID, std::ptr::null_mut::<shmid_ds>());
+            return Err(ShmError::ShmInitFailed);
+        }
+        shminfo.shmaddr = memory_addr;
+        shminfo.read_only = 0;
+
+        // Remote servers, e.g. through `ssh -X`, may announce the extension but fail to attach the segment.
+        // The error arrives asynchronously, and would terminate the process through the default error handler.
+        let attached = {
+            let trap = crate::trap::ErrorTrap::new(display.connection);
+            let attached = XShmAttach(display.connection, shminfo) == 1;
+            !trap.failed() && attached
+        };
+        // Once the server attached the segment, it can be marked for removal.
+        // It stays alive until both sides detached it, and doesn't leak if the process crashes.
+        shmctl(shminfo.shmid, IPC_RMID, std::ptr::null_mut::<shmid_ds>());
+        if attached {
+            Ok(())
+        }else{
+            libc::shmdt(memory_addr as *const c_void);
+            Err(ShmError::ShmAttachFailed)
+        }
+    }
+}
+
+/// Whether the display is connected through a unix socket, i.e. the server runs on this machine
+fn is_local(display: &Display) -> bool {
+    unsafe {
+        let mut address: libc::sockaddr_storage = std::mem::zeroed();
+        let mut length = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        libc::getsockname(XConnectionNumber(display.connection), &mut address as *mut _ as *mut libc::sockaddr, &mut length) == 0
+            && address.ss_family as c_int == libc::AF_UNIX
+    }
+}
+
+/// Create a `memfd` of `capacity` bytes and map it, returning the descriptor and the address of the mapping
+fn map_memfd(capacity: usize) -> Option<(c_int, *mut c_void)> {
+    use libc::{memfd_create, ftruncate, mmap, close, off_t, MFD_CLOEXEC, PROT_READ, PROT_WRITE, MAP_SHARED, MAP_FAILED};
+    unsafe {
+        let fd = memfd_create(b"rxscreen-shm\0".as_ptr() as *const c_char, MFD_CLOEXEC);
+        if fd == -1 {
+            return Option::None;
+        }
+        let address = if ftruncate(fd, capacity as off_t) == 0 {
+            mmap(std::ptr::null_mut(), capacity, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0)
+        }else{
+            MAP_FAILED
+        };
+        if address == MAP_FAILED {
+            close(fd);
+            return Option::None;
+        }
+        Some((fd, address))
+    }
+}
+
+impl Segment {
+    /// The id of the segment on the X server, as reported in `ShmCompletion` events.
+    pub(crate) fn shmseg(&self) -> ShmSeg {
+        self.shminfo.shmseg
+    }
+    /// The padded length of a row of `width` pixels, as `XShmCreateImage` lays it out
+    fn bytes_per_line(&self, width: u32) -> usize {
+        let (pad, bits_per_pixel) = unsafe { ((*self.image.raw).bitmap_pad.max(8) as usize, (*self.image.raw).bits_per_pixel as usize) };
+        (width as usize * bits_per_pixel).div_ceil(pad) * (pad / 8)
+    }
+    /// Whether an image of `area` (width, height) fits into the segment
+    pub(crate) fn fits(&self, area: (u32, u32)) -> bool {
+        self.bytes_per_line(area.0) * area.1 as usize <= self.capacity
+    }
+    /// Shrink or grow the image to `area` (width, height) within the segment, which has to [fit](Segment::fits) it.
+    pub(crate) fn fit(&mut self, area: (u32, u32)) {
+        debug_assert!(self.fits(area));
+        let bytes_per_line = self.bytes_per_line(area.0);
+        unsafe {
+            let ximg = self.image.raw as *mut XImage;
+            (*ximg).width = area.0 as c_int;
+            (*ximg).height = area.1 as c_int;
+            (*ximg).bytes_per_line = bytes_per_line as c_int;
+        }
+    }
+}
+
+// The segment is owned by a single session or image, and Xlib is initialized for threads by `Display::new`.
+unsafe impl Send for Segment {}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe {
+            XShmDetach(self.connection, self.shminfo.as_ref().get_ref());
+            match self.memory {
+                Memory::Memfd => { libc::munmap(self.shminfo.shmaddr as *mut c_void, self.capacity); },
+                Memory::SysV => { libc::shmdt(self.shminfo.shmaddr as *const c_void); }
+            }
+        }
+    }
+}
+
+/// The display of a session, borrowed or owned
+enum DisplayRef<'a> {
+    Borrowed(&'a Display),
+    Owned(Arc<Display>)
+}
+
+impl<'a> Deref for DisplayRef<'a> {
+    type Target = Display;
+    fn deref(&self) -> &Display {
+        match self {
+            DisplayRef::Borrowed(display) => display,
+            DisplayRef::Owned(display) => display
+        }
+    }
 }
 
+/// A segment of a [`SharedSession`], and whether a [`FrameGuard`] holds its image.
+struct Buffer {
+    segment: Segment,
+    busy: AtomicBool
+}
 
 /// Struct dependent on `Display` for Shm
+///
+/// A session captures into a ring of one or more buffers, see [`ShmBuilder::buffers`].
+/// With more than one buffer, frames handed out by [`SharedSession::capture_frame`] can be processed,
+/// e.g. encoded on another thread, while the next frames are captured into the other buffers.
 pub struct SharedSession<'a> {
-    display: &'a Display,
-    shminfo: Pin<Box<XShmSegmentInfo>>,
-    image: Image,
-    offset: (u32, u32),
+    display: DisplayRef<'a>,
+    buffers: Vec<Buffer>,
+    /// The buffer the next capture is tried first
+    next: Cell<usize>,
+    offset: Cell<(u32, u32)>,
     area: (u32, u32),
 }
 impl<'a> SharedSession<'a> {
-    pub fn capture(&self) -> Result<&Image, ()> {
+    /// Capture into the next free buffer of the ring.
+    ///
+    /// The returned image borrows the session, so it is released before the next capture can overwrite it.
+    /// See [`SharedSession::capture_frame`] to hold on to frames while capturing the next ones.
+    #[allow(clippy::result_unit_err)]
+    pub fn capture(&mut self) -> Result<&Image, ()> {
+        self.capture_at(self.offset.get())
+    }
+    /// Capture the area of the session at `offset` (x, y) instead of the configured offset, which is left unchanged.
+    /// See [`SharedSession::set_offset`] to move the session itself.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the area at `offset` reaches past the screen, or `XShmGetImage` failed.
+    #[allow(clippy::result_unit_err)]
+    pub fn capture_at(&mut self, offset: (u32, u32)) -> Result<&Image, ()> {
+        if !self.display.contains(offset, self.area) {
+            return Err(());
+        }
+        let buffer = self.claim().ok_or(())?;
+        if self.capture_into(buffer, offset) {
+            Ok(&buffer.segment.image)
+        }else{
+            Err(())
+        }
+    }
+    /// Capture into the next free buffer of the ring, and hold on to it until the returned guard is dropped.
+    ///
+    /// ```rust,no_run
+    /// # use rxscreen::Display;
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     if let Ok(session) = display.shm().full().buffers(2).build() {
+    ///         std::thread::scope(|scope| {
+    ///             let mut encoder: Option<std::thread::ScopedJoinHandle<()>> = None;
+    ///             for _ in 0..100 {
+    ///                 let frame = session.capture_frame().unwrap();
+    ///                 // Wait for the previous frame, its buffer is free again once its guard is dropped
+    ///                 if let Some(previous) = encoder.take() {
+    ///                     previous.join().unwrap();
+    ///                 }
+    ///                 // Encode `frame` on another thread, while the next one is captured
+    ///                 encoder = Some(scope.spawn(move || {
+    ///                     let pixels = frame.pixels();
+    ///                 }));
+    ///             }
+    ///         });
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ShmError::NoFreeBuffer`] if every buffer is held by a guard, with [`ShmError::OutOfBounds`]
+    /// if the area reaches past the screen, or with [`ShmError::CaptureFailed`] if `XShmGetImage` failed.
+    pub fn capture_frame(&self) -> Result<FrameGuard<'_>, ShmError> {
+        if !self.display.contains(self.offset.get(), self.area) {
+            return Err(ShmError::OutOfBounds);
+        }
+        let buffer = self.claim().ok_or(ShmError::NoFreeBuffer)?;
+        buffer.busy.store(true, Ordering::Release);
+        if self.capture_into(buffer, self.offset.get()) {
+            Ok(FrameGuard { image: &buffer.segment.image, busy: &buffer.busy })
+        }else{
+            buffer.busy.store(false, Ordering::Release);
+            Err(ShmError::CaptureFailed)
+        }
+    }
+    /// The first buffer of the ring, starting at the next one, that isn't held by a guard
+    fn claim(&self) -> Option<&Buffer> {
+        let count = self.buffers.len();
+        (0..count).map(|offset| (self.next.get() + offset) % count)
+            .find(|index| !self.buffers[*index].busy.load(Ordering::Acquire))
+            .map(|index| {
+                self.next.set((index + 1) % count);
+                &self.buffers[index]
+            })
+    }
+    fn capture_into(&self, buffer: &Buffer, offset: (u32, u32)) -> bool {
         unsafe {
-            if XShmGetImage(self.display.connection, self.display.window, self.image.raw, self.offset.0 as i32, self.offset.1 as i32, AllPlanes) == 1 {
-                Ok(&self.image)
+            XShmGetImage(self.display.connection, self.display.window, buffer.segment.image.raw, offset.0 as i32, offset.1 as i32, AllPlanes) == 1
+        }
+    }
+    /// Move the session to capture at `offset` (x, y) from now on, e.g. to follow a moving window.
+    /// The buffers are kept as they are. Captures fail while the area reaches past the screen.
+    pub fn set_offset(&self, offset: (u32, u32)) {
+        self.offset.set(offset);
+    }
+    /// Capture `area` (width, height) from now on.
+    ///
+    /// Buffers are only reallocated if `area` doesn't fit into their segments,
+    /// shrinking or growing back to at most the largest area so far reuses them.
+    /// The images of previous captures are resized along with their buffers.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a segment for `area` couldn't be allocated. The session keeps capturing its previous area then.
+    pub fn resize(&mut self, area: (u32, u32)) -> Result<(), ShmError> {
+        // Allocate the segments that are too small first, so a failure leaves the session untouched
+        let grown = self.buffers.iter()
+            .map(|buffer| if buffer.segment.fits(area) { Ok(Option::None) } else { Segment::create(&self.display, area).map(Some) })
+            .collect::<Result<Vec<Option<Segment>>, ShmError>>()?;
+        for (buffer, segment) in self.buffers.iter_mut().zip(grown) {
+            if let Some(segment) = segment {
+                buffer.segment = segment;
             }else{
-                Err(())
+                buffer.segment.fit(area);
             }
         }
+        self.area = area;
+        Ok(())
+    }
+    /// The amount of buffers in the ring
+    pub fn buffers(&self) -> usize {
+        self.buffers.len()
+    }
+    /// Capture at `fps` frames per second, see [`CaptureLoop`](crate::clock::CaptureLoop)
+    pub fn capture_loop(&mut self, fps: u32) -> crate::clock::CaptureLoop<&mut SharedSession<'a>> {
+        crate::clock::CaptureLoop::new(self, fps)
     }
     pub fn offset(&self) -> (u32, u32) {
-        self.offset
+        self.offset.get()
     }
     pub fn area(&self) -> (u32, u32) {
         self.area
     }
+    /// The display the session captures
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+}
+
+/// A [`SharedSession`] owning its display, see [`ShmBuilder::owned`]
+pub type OwnedSession = SharedSession<'static>;
+
+/// A frame captured through [`SharedSession::capture_frame`].
+///
+/// The buffer of the frame isn't captured into until the guard is dropped.
+/// Guards can be sent to other threads, e.g. to encode the frame there.
+pub struct FrameGuard<'s> {
+    image: &'s Image,
+    busy: &'s AtomicBool
+}
+
+impl<'s> Deref for FrameGuard<'s> {
+    type Target = Image;
+    fn deref(&self) -> &Image {
+        self.image
+    }
 }
-impl<'a> Drop for SharedSession<'a> {
+
+impl<'s> Drop for FrameGuard<'s> {
     fn drop(&mut self) {
-        use libc::{shmdt, shmctl, shmid_ds, IPC_RMID};
+        self.busy.store(false, Ordering::Release);
+    }
+}
+
+/// A writable image in shared memory, which can be presented on any window through `XShmPutImage`.
+///
+/// Pixels written into [`SharedImage::image_mut`] are read directly by the X server, without
+/// being copied through the connection. As the server reads the segment asynchronously, the image
+/// must not be modified until the `ShmCompletion` event for the last [`SharedImage::put`] arrived,
+/// which [`SharedImage::image_mut`] and [`SharedImage::wait`] take care of.
+///
+/// ```rust,no_run
+/// # use rxscreen::Display;
+/// if let Ok(display) = Display::new(":0.0") {
+///     if let Ok(mut frame) = display.shm().area((0, 0), (1920, 1080)).build_image() {
+///         // Fill the image with gray
+///         unsafe { frame.image_mut().as_bytes_mut() }.iter_mut().for_each(|byte| *byte = 0x80);
+///         # let window = 0;
+///         // Present it on `window`, and wait until the server has read the segment
+///         frame.put(window, (0, 0));
+///         frame.wait();
+///     }
+/// }
+/// ```
+pub struct SharedImage<'a> {
+    display: DisplayRef<'a>,
+    segment: Segment,
+    gc: GC,
+    pending: bool
+}
+
+/// Identifies the `ShmCompletion` event [`SharedImage::wait`] waits for
+#[repr(C)]
+struct CompletionFilter {
+    event_type: c_int,
+    shmseg: ShmSeg
+}
+
+unsafe extern "C" fn is_completion(_display: XDisplay, event: *mut events::XEvent, arg: XPointer) -> c_int {
+    let filter = &*(arg as *const CompletionFilter);
+    let event = &*(event as *const events::XShmCompletionEvent);
+    (event.type_ == filter.event_type && event.shmseg == filter.shmseg) as c_int
+}
+
+impl<'a> SharedImage<'a> {
+    /// The image inside the shared segment, for reading.
+    pub fn image(&self) -> &Image {
+        &self.segment.image
+    }
+    /// The image inside the shared segment, for writing.
+    ///
+    /// Blocks until the X server finished reading the segment, if a [`SharedImage::put`] is still in flight.
+    pub fn image_mut(&mut self) -> &mut Image {
+        self.wait();
+        &mut self.segment.image
+    }
+    /// Present the whole image on `window` (or any other drawable of the default depth) at `position`.
+    ///
+    /// The request is sent asynchronously, see [`SharedImage::wait`].
+    pub fn put(&mut self, window: XWindow, position: (i32, i32)) {
+        let (width, height) = (self.segment.image.width() as u32, self.segment.image.height() as u32);
+        self.put_area(window, (0, 0), (width, height), position);
+    }
+    /// Present the area at `source` (x, y) with `size` (width, height) of the image on `window` at `position`.
+    pub fn put_area(&mut self, window: XWindow, source: (i32, i32), size: (u32, u32), position: (i32, i32)) {
+        self.wait();
+        unsafe {
+            XShmPutImage(self.display.connection, window, self.gc, self.segment.image.raw,
+                source.0, source.1, position.0, position.1, size.0, size.1, 1);
+            XFlush(self.display.connection);
+        }
+        self.pending = true;
+    }
+    /// Block until the `ShmCompletion` event of the last [`SharedImage::put`] arrived,
+    /// after which the image can be safely modified again.
+    ///
+    /// Other events are left in the queue of the display.
+    pub fn wait(&mut self) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+        let Some(event_type) = self.display.extension_events().shm else {
+            // Without the event base we can't match the completion, fall back to a round-trip.
+            unsafe { XSync(self.display.connection, 0) };
+            return;
+        };
+        let filter = CompletionFilter { event_type, shmseg: self.segment.shmseg() };
         unsafe {
-            XShmDetach(self.display.connection, self.shminfo.as_ref().get_ref());
-            shmdt((*self.shminfo).shmaddr as *const c_void);
-            shmctl((*self.shminfo).shmid, IPC_RMID, 0 as *mut shmid_ds);
+            let mut xev = events::XEvent { r#type: 0 };
+            XIfEvent(self.display.connection, &mut xev, is_completion, &filter as *const CompletionFilter as XPointer);
+        }
+    }
+    /// Mark the last [`SharedImage::put`] as completed if `event` is its `ShmCompletion`, returning whether it was.
+    ///
+    /// Loops draining the events of the display, e.g. through [`Display::poll_event`], have to pass them here,
+    /// otherwise [`SharedImage::wait`] blocks for a completion that has already been taken off the queue.
+    pub fn handle_event(&mut self, event: &crate::event::Event) -> bool {
+        match event {
+            crate::event::Event::ShmCompletion { shmseg, .. } if self.pending && *shmseg == self.segment.shmseg() => {
+                self.pending = false;
+                true
+            },
+            _ => false
+        }
+    }
+    /// Whether a [`SharedImage::put`] is still waiting for its completion.
+    pub fn busy(&self) -> bool {
+        self.pending
+    }
+}
+
+impl<'a> Drop for SharedImage<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            // The server may still read the segment, which gets detached right after.
+            XSync(self.display.connection, 0);
+            XFreeGC(self.display.connection, self.gc);
         }
     }
 }
 
 pub struct ShmBuilder<'a> {
-    display: &'a Display,
+    display: DisplayRef<'a>,
     offset: (u32, u32),
-    area: (u32, u32)
+    area: (u32, u32),
+    buffers: usize
+}
+
+impl ShmBuilder<'static> {
+    /// Configure a session that owns `display`, taken by value or shared through an [`Arc`].
+    /// The session is an [`OwnedSession`], see [threads](self#threads).
+    /// ```rust
+    /// # use rxscreen::{Display, shm::{OwnedSession, ShmBuilder}};
+    /// # use std::sync::Arc;
+    /// struct Service {
+    ///     display: Arc<Display>,
+    ///     session: OwnedSession
+    /// }
+    /// if let Ok(display) = Display::new(":0.0") {
+    ///     let display = Arc::new(display);
+    ///     if let Ok(session) = ShmBuilder::owned(display.clone()).full().build() {
+    ///         let service = Service { display, session };
+    ///     }
+    /// }
+    /// ```
+    pub fn owned(display: impl Into<Arc<Display>>) -> Self {
+        ShmBuilder {
+            display: DisplayRef::Owned(display.into()),
+            area: (0, 0),
+            offset: (0, 0),
+            buffers: 1
+        }
+    }
 }
 
 impl<'a> ShmBuilder<'a> {
@@ -110,7 +643,7 @@ impl<'a> ShmBuilder<'a> {
     ///
     pub fn full(mut self) -> Self {
         self.offset = (0, 0);
-        self.area = (self.display.width as u32, self.display.height as u32);
+        self.area = (self.display.width, self.display.height);
         self
     }
     /// Set the offset (x, y) and the area (width, height) at which the shared session
@@ -120,12 +653,18 @@ impl<'a> ShmBuilder<'a> {
         self.area = area;
         self
     }
+    /// Set the amount of buffers the session captures into, one by default.
+    /// See [`SharedSession::capture_frame`].
+    pub fn buffers(mut self, buffers: usize) -> Self {
+        self.buffers = buffers.max(1);
+        self
+    }
     /// Initialize the session with the configured parameters.
     /// Returns `None` if the initialization failed.
     /// ```rust
     /// # use rxscreen::{Display, ShmBuilder};
     /// if let Ok(display) = Display::new(":0.0") {
-    ///    if let Some(shm) = display.shm()
+    ///    if let Some(mut shm) = display.shm()
     ///             .full()
     ///             .build() {
     ///             let capture = shm.capture()
@@ -136,68 +675,132 @@ impl<'a> ShmBuilder<'a> {
     ///     }
     /// }
     pub fn build(self) -> Result<SharedSession<'a>, ShmError> {
-        use shm::*;
-        unsafe {
-            use libc::{shmget, shmat};
-            use libc::{IPC_PRIVATE, IPC_CREAT};
-
-            if XShmQueryExtension(self.display.connection) {
-                let vis = XDefaultVisual(self.display.connection, 0);
-
-                //let mut shminfo = libc::malloc(std::mem::size_of::<XShmSegmentInfo>()) as *mut XShmSegmentInfo;
-                //libc::memset(shminfo as *mut c_void, 0, std::mem::size_of::<XShmSegmentInfo>());
-                let mut shminfo = Box::pin(XShmSegmentInfo { shmseg: 0, shmid: 0, shmaddr: std::ptr::null(), read_only: 0});
-
-                let ximg = XShmCreateImage(self.display.connection, vis, (*vis).bits_per_rgb as u32, ZPixmap as i32, std::ptr::null(), shminfo.as_ref().get_ref(), self.area.0, self.area.1) as *mut XImage;
-
-                shminfo.shmid = shmget(IPC_PRIVATE, ((*ximg).bytes_per_line * (*ximg).height) as usize, IPC_CREAT|0o600);
-                if shminfo.shmid == -1 {
-                    return Err(ShmError::ShmInitFailed);
-                }
-
-                let memory_addr = shmat((*shminfo).shmid, 0 as *const c_void, 0) as *mut c_char;
-                shminfo.shmaddr = memory_addr;
-                (*ximg).data = memory_addr;
-                shminfo.read_only = 0;
-
-                if XShmAttach(self.display.connection, shminfo.as_ref().get_ref()) == 1 {
-                    let shared = SharedSession {
-                        display: self.display,
-                        shminfo,
-                        image: Image {
-                            raw: ximg
-                        },
-                        offset: self.offset,
-                        area: self.area
-                    };
-                    Ok(shared)
-                }else{
-                    Err(ShmError::ShmAttachFailed)
-                }
-            }else{
-                Err(ShmError::ExtensionNotAvailable)
-            }
+        let buffers = (0..self.buffers)
+            .map(|_| Segment::create(&self.display, self.area).map(|segment| Buffer { segment, busy: AtomicBool::new(false) }))
+            .collect::<Result<Vec<Buffer>, ShmError>>()?;
+        Ok(SharedSession {
+            display: self.display,
+            buffers,
+            next: Cell::new(0),
+            offset: Cell::new(self.offset),
+            area: self.area
+        })
+    }
+    /// Allocate a [`SharedImage`] with the configured area (width, height), to present
+    /// pixels on windows through `XShmPutImage`. The configured offset is ignored.
+    pub fn build_image(self) -> Result<SharedImage<'a>, ShmError> {
+        let segment = Segment::create(&self.display, self.area)?;
+        let gc = unsafe { XCreateGC(self.display.connection, self.display.window, 0, std::ptr::null()) };
+        if gc.is_null() {
+            return Err(ShmError::ShmInitFailed);
         }
+        Ok(SharedImage {
+            display: self.display,
+            segment,
+            gc,
+            pending: false
+        })
     }
 }
 
 
 impl crate::Display {
-    pub fn shm(&self) -> ShmBuilder {
+    pub fn shm(&self) -> ShmBuilder<'_> {
         ShmBuilder {
             area: (0, 0),
             offset: (0, 0),
-            display: self
+            buffers: 1,
+            display: DisplayRef::Borrowed(self)
         }
     }
 }
 
 #[test]
 fn can_create_shm_sessions() {
-    let display = Display::new(":0").unwrap();
-    let shm = display.shm()
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut shm = display.shm()
+        .area((0, 0), (100, 100))
+        .build()
+        .unwrap();
+    assert!(shm.capture().is_ok());
+}
+
+#[test]
+fn refuses_to_capture_past_the_screen() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut shm = display.shm()
         .area((0, 0), (100, 100))
         .build()
         .unwrap();
+    assert!(shm.capture_at((300, 0)).is_err());
+    shm.set_offset((0, 200));
+    assert!(shm.capture().is_err());
+    assert_eq!(shm.capture_frame().err(), Some(ShmError::OutOfBounds));
+    shm.set_offset((220, 140));
     assert!(shm.capture().is_ok());
 }
+
+#[test]
+fn can_create_shared_images() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut shared = display.shm()
+        .area((0, 0), (100, 100))
+        .build_image()
+        .unwrap();
+    assert!(!shared.busy());
+    assert_eq!(unsafe { shared.image_mut().as_bytes_mut() }.len(), 100 * 100 * 4);
+}
+
+#[test]
+fn can_capture_into_buffer_rings() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let shm = display.shm()
+        .area((0, 0), (100, 100))
+        .buffers(2)
+        .build()
+        .unwrap();
+    let first = shm.capture_frame().unwrap();
+    let second = shm.capture_frame().unwrap();
+    assert_eq!(shm.capture_frame().err(), Some(ShmError::NoFreeBuffer));
+    drop(first);
+    assert!(shm.capture_frame().is_ok());
+    drop(second);
+}
+
+#[test]
+fn can_retarget_shm_sessions() {
+    let Some(display) = crate::headless::test_display(320, 240) else { return };
+    let mut shm = display.shm()
+        .area((0, 0), (100, 100))
+        .build()
+        .unwrap();
+    shm.set_offset((10, 10));
+    assert_eq!(shm.offset(), (10, 10));
+    assert!(shm.capture_at((20, 20)).is_ok());
+    assert_eq!(shm.offset(), (10, 10));
+
+    // Shrinking reuses the segment, growing beyond it reallocates
+    shm.resize((50, 20)).unwrap();
+    assert_eq!(unsafe { shm.capture().unwrap().as_bytes() }.len(), 50 * 20 * 4);
+    shm.resize((200, 100)).unwrap();
+    assert_eq!(unsafe { shm.capture().unwrap().as_bytes() }.len(), 200 * 100 * 4);
+}
+
+#[test]
+fn owned_sessions_are_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<OwnedSession>();
+    assert_send::<FrameGuard<'static>>();
+}
+
+#[test]
+fn can_map_memfd_segments() {
+    let (fd, address) = map_memfd(4096).unwrap();
+    unsafe {
+        let memory = std::slice::from_raw_parts_mut(address as *mut u8, 4096);
+        memory[4095] = 0xff;
+        assert_eq!(memory[4095], 0xff);
+        libc::munmap(address, 4096);
+        libc::close(fd);
+    }
+}
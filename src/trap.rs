@@ -0,0 +1,103 @@
+//!
+//! Trapping the errors of requests, which would otherwise terminate the process through the default
+//! error handler of Xlib. Errors arrive asynchronously, so the requests are synced before checking.
+//!
+//! Xlib has a single error handler for the whole process, so only one trap can be active at a time:
+//! trapped calls are serialized process-wide, across every display and thread. While a trap is active,
+//! errors of other connections, and of requests sent before the trap, are passed to the previous handler.
+//!
+
+use core::ffi::c_int;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::ffi::{XDisplay, XErrorHandler, XNextRequest, XSetErrorHandler, XSync, events::XErrorEvent};
+
+/// Serializes the replacement of the process wide error handler
+static LOCK: Mutex<()> = Mutex::new(());
+/// The connection of the active trap
+static CONNECTION: AtomicUsize = AtomicUsize::new(0);
+/// The serial of the first request the active trap covers
+static START: AtomicUsize = AtomicUsize::new(0);
+/// The handler the active trap replaced, `0` for none
+static PREVIOUS: AtomicUsize = AtomicUsize::new(0);
+static TRAPPED: AtomicBool = AtomicBool::new(false);
+
+type Handler = unsafe extern "C" fn(display: XDisplay, event: *mut XErrorEvent) -> c_int;
+
+unsafe extern "C" fn trap_error(display: XDisplay, event: *mut XErrorEvent) -> c_int {
+    if display as usize == CONNECTION.load(Ordering::SeqCst) && (*event).serial as usize >= START.load(Ordering::SeqCst) {
+        TRAPPED.store(true, Ordering::SeqCst);
+        return 0;
+    }
+    match PREVIOUS.load(Ordering::SeqCst) {
+        0 => 0,
+        previous => {
+            let previous: Handler = std::mem::transmute(previous);
+            previous(display, event)
+        }
+    }
+}
+
+/// Records the errors of the requests sent on a connection instead of the previous error handler, until it is dropped
+pub(crate) struct ErrorTrap {
+    connection: XDisplay,
+    previous: XErrorHandler,
+    _lock: MutexGuard<'static, ()>
+}
+
+impl ErrorTrap {
+    /// Trap the errors of the requests sent on `connection` from now on
+    pub(crate) fn new(connection: XDisplay) -> Self {
+        let lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        CONNECTION.store(connection as usize, Ordering::SeqCst);
+        START.store(unsafe { XNextRequest(connection) } as usize, Ordering::SeqCst);
+        TRAPPED.store(false, Ordering::SeqCst);
+        let previous = unsafe { XSetErrorHandler(Some(trap_error)) };
+        PREVIOUS.store(previous.map_or(0, |handler| handler as usize), Ordering::SeqCst);
+        Self { connection, previous, _lock: lock }
+    }
+    /// Wait until the server processed the requests sent on the connection,
+    /// and whether any of them failed since the last check
+    pub(crate) fn failed(&self) -> bool {
+        unsafe { XSync(self.connection, 0) };
+        TRAPPED.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for ErrorTrap {
+    fn drop(&mut self) {
+        unsafe { XSetErrorHandler(self.previous) };
+        CONNECTION.store(0, Ordering::SeqCst);
+        PREVIOUS.store(0, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn traps_the_errors_of_its_connection_only() {
+    static FORWARDED: AtomicUsize = AtomicUsize::new(0);
+    unsafe extern "C" fn forward(_display: XDisplay, _event: *mut XErrorEvent) -> c_int {
+        FORWARDED.fetch_add(1, Ordering::SeqCst);
+        0
+    }
+    let _lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (trapped, other) = (0x1000 as XDisplay, 0x2000 as XDisplay);
+    CONNECTION.store(trapped as usize, Ordering::SeqCst);
+    START.store(10, Ordering::SeqCst);
+    PREVIOUS.store(forward as Handler as usize, Ordering::SeqCst);
+    TRAPPED.store(false, Ordering::SeqCst);
+    let error = |serial| XErrorEvent {
+        type_: 0, display: std::ptr::null_mut(), resourceid: 0, serial, error_code: 8, request_code: 130, minor_code: 4
+    };
+
+    unsafe {
+        // A request sent before the trap started, and one of another connection
+        trap_error(trapped, &mut error(9));
+        trap_error(other, &mut error(12));
+        assert_eq!((TRAPPED.load(Ordering::SeqCst), FORWARDED.load(Ordering::SeqCst)), (false, 2));
+        trap_error(trapped, &mut error(10));
+        assert_eq!((TRAPPED.load(Ordering::SeqCst), FORWARDED.load(Ordering::SeqCst)), (true, 2));
+    }
+    CONNECTION.store(0, Ordering::SeqCst);
+    PREVIOUS.store(0, Ordering::SeqCst);
+    TRAPPED.store(false, Ordering::SeqCst);
+}
@@ -0,0 +1,71 @@
+//! Reading the `MIT-MAGIC-COOKIE-1` of a display from the Xauthority file
+
+use std::path::PathBuf;
+
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+const COOKIE: &[u8] = b"MIT-MAGIC-COOKIE-1";
+
+/// The (name, data) of the cookie for the local display `number`, if the Xauthority file has one
+pub(super) fn cookie(number: u32) -> Option<(Vec<u8>, Vec<u8>)> {
+    let path = match std::env::var_os("XAUTHORITY") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".Xauthority")
+    };
+    let data = std::fs::read(path).ok()?;
+    find(&data, &hostname()?, number)
+}
+
+fn hostname() -> Option<Vec<u8>> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return None;
+    }
+    let length = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+    Some(name[..length].to_vec())
+}
+
+/// Find the cookie for the display `number` on `hostname` in the Xauthority file `data`.
+/// Like Xlib, the first matching entry wins, and entries without a display number match every display.
+fn find(mut data: &[u8], hostname: &[u8], number: u32) -> Option<(Vec<u8>, Vec<u8>)> {
+    let number = number.to_string();
+    while data.len() >= 2 {
+        let family = u16::from_be_bytes([data[0], data[1]]);
+        data = &data[2..];
+        let mut fields = [&[][..]; 4];
+        for field in &mut fields {
+            let length = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+            *field = data.get(2..2 + length)?;
+            data = &data[2 + length..];
+        }
+        let [address, display, name, cookie] = fields;
+        let host = family == FAMILY_WILD || (family == FAMILY_LOCAL && address == hostname);
+        if host && (display.is_empty() || display == number.as_bytes()) && name == COOKIE {
+            return Some((name.to_vec(), cookie.to_vec()));
+        }
+    }
+    None
+}
+
+#[test]
+fn finds_the_cookie_of_the_display() {
+    fn entry(family: u16, fields: [&[u8]; 4]) -> Vec<u8> {
+        let mut entry = family.to_be_bytes().to_vec();
+        for field in fields {
+            entry.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            entry.extend_from_slice(field);
+        }
+        entry
+    }
+    let file = [
+        entry(FAMILY_LOCAL, [b"other", b"0", COOKIE, &[1; 16]]),
+        entry(FAMILY_LOCAL, [b"host", b"1", COOKIE, &[2; 16]]),
+        entry(FAMILY_LOCAL, [b"host", b"0", b"XDM-AUTHORIZATION-1", &[3; 16]]),
+        entry(FAMILY_LOCAL, [b"host", b"0", COOKIE, &[4; 16]]),
+        entry(FAMILY_WILD, [b"", b"", COOKIE, &[5; 16]])
+    ].concat();
+    assert_eq!(find(&file, b"host", 0), Some((COOKIE.to_vec(), vec![4; 16])));
+    assert_eq!(find(&file, b"host", 1), Some((COOKIE.to_vec(), vec![2; 16])));
+    assert_eq!(find(&file, b"elsewhere", 7), Some((COOKIE.to_vec(), vec![5; 16])));
+    assert_eq!(find(&file[..file.len() - 1], b"elsewhere", 7), None);
+}
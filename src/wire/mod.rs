@@ -0,0 +1,483 @@
+//!
+//! A backend speaking the X11 protocol directly, without the X client libraries.
+//!
+//! A [`Connection`] connects to a local X server through its unix socket, authenticating with the
+//! `MIT-MAGIC-COOKIE-1` of the Xauthority file (`$XAUTHORITY`, or `~/.Xauthority`).
+//! It captures through `GetImage` or MIT-SHM (see [`Connection::shm`]), and queries the pointer
+//! and the RandR monitors, so it suits static builds and containers without libX11.
+//! Combined with the `dlopen` feature, nothing of the crate links the X libraries.
+//!
+//! Captures are returned as [`WireImage`], holding the pixels in BGRX like [`Image`](crate::Image).
+//! Only servers with 24 bit color in 32 bit pixels, as virtually every server today, are supported.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::wire::Connection;
+//! if let Ok(mut connection) = Connection::open(":0") {
+//!     let capture = connection.capture().unwrap();
+//!     let (x, y) = connection.root_mouse_position().unwrap();
+//!     for monitor in connection.monitors().unwrap() {
+//!         println!("{}: {}x{} at {}x{}", monitor.name(), monitor.width(), monitor.height(), monitor.x(), monitor.y());
+//!     }
+//! }
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use crate::codec::Pixels;
+
+mod auth;
+mod randr;
+mod shm;
+
+pub use randr::Monitor;
+pub use shm::ShmCapture;
+
+#[derive(Debug)]
+pub enum WireError {
+    Io(io::Error),
+    /// The display name couldn't be parsed, only local displays (e.g. `:0`, `unix:0.1`) are supported
+    InvalidDisplay,
+    /// The server refused the connection, for the given reason
+    Refused(String),
+    /// The server answered a request with an error
+    Request { code: u8, major: u8, minor: u16 },
+    ExtensionNotAvailable,
+    /// The server doesn't store pixels as 32 bit BGRX
+    UnsupportedFormat,
+    /// The server sent data that isn't valid X11
+    Protocol
+}
+
+impl From<io::Error> for WireError {
+    fn from(error: io::Error) -> Self {
+        WireError::Io(error)
+    }
+}
+
+/// The screen a [`Connection`] captures
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub root: u32,
+    pub width: u16,
+    pub height: u16,
+    pub root_depth: u8
+}
+
+/// A connection to an X server, see the [module documentation](self).
+pub struct Connection {
+    stream: UnixStream,
+    /// The sequence number of the last request
+    sequence: u16,
+    screen: Screen,
+    /// Whether images of the root depth are 32 bit BGRX
+    bgrx: bool,
+    id_base: u32,
+    id_mask: u32,
+    next_id: u32,
+    /// The major opcodes of the extensions queried so far
+    extensions: HashMap<&'static str, Option<u8>>,
+    /// Whether RandR 1.5 has been negotiated, which monitors need
+    randr_version: bool
+}
+
+/// Reads little endian values from a response
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Self { data, position }
+    }
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], WireError> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or(WireError::Protocol)?;
+        self.position += length;
+        Ok(bytes)
+    }
+    fn skip(&mut self, length: usize) -> Result<(), WireError> {
+        self.bytes(length).map(|_| ())
+    }
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, WireError> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+    fn i16(&mut self) -> Result<i16, WireError> {
+        Ok(self.u16()? as i16)
+    }
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Round `length` up to a multiple of 4, as X11 pads every string and request
+fn pad(length: usize) -> usize {
+    length.div_ceil(4) * 4
+}
+
+impl Connection {
+    /// Connect to the display named `display`, e.g. `:0` or `unix:1.0`
+    pub fn open(display: &str) -> Result<Self, WireError> {
+        let (number, screen) = parse_display(display).ok_or(WireError::InvalidDisplay)?;
+        let path = format!("/tmp/.X11-unix/X{}", number);
+        let stream = match UnixStream::connect(&path) {
+            Ok(stream) => stream,
+            Err(error) => connect_abstract(&path).ok_or(error)?
+        };
+        Self::setup(stream, auth::cookie(number), screen)
+    }
+    /// Connect to the display named by the `DISPLAY` environment variable
+    pub fn from_env() -> Result<Self, WireError> {
+        Self::open(&std::env::var("DISPLAY").map_err(|_| WireError::InvalidDisplay)?)
+    }
+
+    /// Perform the connection setup on `stream`, authenticating with the (name, data) of `cookie`
+    fn setup(mut stream: UnixStream, cookie: Option<(Vec<u8>, Vec<u8>)>, screen: usize) -> Result<Self, WireError> {
+        let (name, data) = cookie.unwrap_or_default();
+        let mut request = vec![b'l', 0];
+        request.extend_from_slice(&11u16.to_le_bytes());
+        request.extend_from_slice(&0u16.to_le_bytes());
+        request.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        request.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        request.extend_from_slice(&[0, 0]);
+        for field in [&name, &data] {
+            request.extend_from_slice(field);
+            request.resize(pad(request.len()), 0);
+        }
+        stream.write_all(&request)?;
+
+        let mut header = [0; 8];
+        stream.read_exact(&mut header)?;
+        let mut body = vec![0; u16::from_le_bytes([header[6], header[7]]) as usize * 4];
+        stream.read_exact(&mut body)?;
+        if header[0] != 1 {
+            let reason = body.get(..header[1] as usize).unwrap_or(&body);
+            return Err(WireError::Refused(String::from_utf8_lossy(reason).trim_end().to_owned()));
+        }
+
+        let mut reader = Reader::new(&body, 0);
+        reader.skip(4)?;
+        let (id_base, id_mask) = (reader.u32()?, reader.u32()?);
+        reader.skip(4)?;
+        let vendor = reader.u16()? as usize;
+        reader.skip(2)?;
+        let (screens, formats) = (reader.u8()? as usize, reader.u8()? as usize);
+        let image_byte_order = reader.u8()?;
+        reader.skip(9)?;
+        reader.skip(pad(vendor))?;
+        let mut pixmap_formats = Vec::with_capacity(formats);
+        for _ in 0..formats {
+            pixmap_formats.push((reader.u8()?, reader.u8()?));
+            reader.skip(6)?;
+        }
+
+        let mut selected = None;
+        for index in 0..screens {
+            let root = reader.u32()?;
+            reader.skip(16)?;
+            let (width, height) = (reader.u16()?, reader.u16()?);
+            reader.skip(14)?;
+            let root_depth = reader.u8()?;
+            let depths = reader.u8()?;
+            for _ in 0..depths {
+                reader.skip(2)?;
+                let visuals = reader.u16()? as usize;
+                reader.skip(4 + visuals * 24)?;
+            }
+            if index == screen {
+                selected = Some(Screen { root, width, height, root_depth });
+            }
+        }
+        let screen = selected.ok_or(WireError::InvalidDisplay)?;
+        let bgrx = image_byte_order == 0 && pixmap_formats.contains(&(screen.root_depth, 32)) && screen.root_depth == 24;
+
+        Ok(Self {
+            stream,
+            sequence: 0,
+            screen,
+            bgrx,
+            id_base,
+            id_mask,
+            next_id: 1,
+            extensions: HashMap::new(),
+            randr_version: false
+        })
+    }
+
+    /// The screen of the display
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Send a request with `opcode`, the `data` byte of its header and `body`, returning its sequence number
+    fn request(&mut self, opcode: u8, data: u8, body: &[u8]) -> Result<u16, WireError> {
+        let length = 4 + pad(body.len());
+        let mut request = Vec::with_capacity(length);
+        request.extend_from_slice(&[opcode, data]);
+        request.extend_from_slice(&((length / 4) as u16).to_le_bytes());
+        request.extend_from_slice(body);
+        request.resize(length, 0);
+        self.stream.write_all(&request)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(self.sequence)
+    }
+
+    /// Wait for the reply to the request `sequence`.
+    /// Events are skipped, and any error fails, as requests are only sent one at a time.
+    fn reply(&mut self, sequence: u16) -> Result<Vec<u8>, WireError> {
+        loop {
+            let mut response = vec![0; 32];
+            self.stream.read_exact(&mut response)?;
+            let mut reader = Reader::new(&response, 0);
+            let (kind, detail, number) = (reader.u8()?, reader.u8()?, reader.u16()?);
+            match kind {
+                0 => {
+                    reader.skip(4)?;
+                    let (minor, major) = (reader.u16()?, reader.u8()?);
+                    return Err(WireError::Request { code: detail, major, minor });
+                },
+                // Replies, and generic events, carry additional data
+                1 | 35 => {
+                    let extra = reader.u32()? as usize * 4;
+                    response.resize(32 + extra, 0);
+                    self.stream.read_exact(&mut response[32..])?;
+                    if kind == 1 && number == sequence {
+                        return Ok(response);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait until the server processed every request sent so far, failing with the error of any of them
+    fn roundtrip(&mut self) -> Result<(), WireError> {
+        // GetInputFocus
+        let sequence = self.request(43, 0, &[])?;
+        self.reply(sequence).map(|_| ())
+    }
+
+    /// Allocate an id for a new resource, e.g. a shared memory segment
+    fn generate_id(&mut self) -> u32 {
+        let id = self.id_base | ((self.next_id << self.id_mask.trailing_zeros()) & self.id_mask);
+        self.next_id += 1;
+        id
+    }
+
+    /// The major opcode of the extension `name`, or `None` if the server lacks it
+    fn extension(&mut self, name: &'static str) -> Result<Option<u8>, WireError> {
+        if let Some(opcode) = self.extensions.get(name) {
+            return Ok(*opcode);
+        }
+        let mut body = (name.len() as u16).to_le_bytes().to_vec();
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(name.as_bytes());
+        // QueryExtension
+        let sequence = self.request(98, 0, &body)?;
+        let reply = self.reply(sequence)?;
+        let opcode = if reply[8] == 1 { Some(reply[9]) } else { None };
+        self.extensions.insert(name, opcode);
+        Ok(opcode)
+    }
+
+    /// Take a screenshot of the screen
+    pub fn capture(&mut self) -> Result<WireImage, WireError> {
+        self.capture_area((0, 0), (self.screen.width as u32, self.screen.height as u32))
+    }
+
+    /// Take a screenshot of the area of `size` (width, height) at `offset` (x, y)
+    pub fn capture_area(&mut self, offset: (u32, u32), size: (u32, u32)) -> Result<WireImage, WireError> {
+        if !self.bgrx {
+            return Err(WireError::UnsupportedFormat);
+        }
+        let mut body = self.screen.root.to_le_bytes().to_vec();
+        for value in [offset.0, offset.1, size.0, size.1] {
+            body.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        body.extend_from_slice(&u32::MAX.to_le_bytes());
+        // GetImage in ZPixmap format
+        let sequence = self.request(73, 2, &body)?;
+        let mut reply = self.reply(sequence)?;
+        let length = size.0 as usize * size.1 as usize * 4;
+        if reply.len() < 32 + length {
+            return Err(WireError::Protocol);
+        }
+        reply.drain(..32);
+        reply.truncate(length);
+        Ok(WireImage { width: size.0 as usize, height: size.1 as usize, data: reply })
+    }
+
+    /// Query the pointer position relative to the root window
+    pub fn root_mouse_position(&mut self) -> Result<(i32, i32), WireError> {
+        // QueryPointer
+        let sequence = self.request(38, 0, &self.screen.root.to_le_bytes())?;
+        let reply = self.reply(sequence)?;
+        let mut reader = Reader::new(&reply, 16);
+        Ok((reader.i16()? as i32, reader.i16()? as i32))
+    }
+
+    /// The name of `atom`
+    fn atom_name(&mut self, atom: u32) -> Result<String, WireError> {
+        // GetAtomName
+        let sequence = self.request(17, 0, &atom.to_le_bytes())?;
+        let reply = self.reply(sequence)?;
+        let length = Reader::new(&reply, 8).u16()? as usize;
+        Ok(String::from_utf8_lossy(Reader::new(&reply, 32).bytes(length)?).into_owned())
+    }
+}
+
+/// Parse the display number and screen of a local display name, e.g. `:0`, `:1.0` or `unix:0`
+fn parse_display(name: &str) -> Option<(u32, usize)> {
+    let (host, display) = name.rsplit_once(':')?;
+    if !(host.is_empty() || host == "unix") {
+        return None;
+    }
+    let (number, screen) = display.split_once('.').unwrap_or((display, "0"));
+    Some((number.parse().ok()?, screen.parse().ok()?))
+}
+
+/// Connect to the abstract socket of the display, which servers on Linux listen on as well
+fn connect_abstract(path: &str) -> Option<UnixStream> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(path).ok()?;
+        UnixStream::connect_addr(&address).ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// A capture taken through a [`Connection`], in BGRX
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>
+}
+
+impl WireImage {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixels(&self) -> Pixels<'_> {
+        Pixels::new(&self.data, self.width, self.height, self.width * 4).expect("image holds all of its pixels")
+    }
+    /// The pixels in BGRX, row by row without padding
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Connect to a fake server on a socket pair, which completes the setup for a single screen of
+/// 640x480 at depth 24 with root window `0x1234`, and then runs `script`
+#[cfg(test)]
+fn fake_server(script: impl FnOnce(&mut UnixStream) + Send + 'static) -> (Connection, std::thread::JoinHandle<()>) {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut request = [0; 12];
+        server.read_exact(&mut request).unwrap();
+        assert_eq!(&request[..4], &[b'l', 0, 11, 0]);
+
+        // 32 bits per pixel at depth 24
+        let mut setup = vec![0; 32];
+        setup[4..8].copy_from_slice(&0x0040_0000u32.to_le_bytes());
+        setup[8..12].copy_from_slice(&0x001f_ffffu32.to_le_bytes());
+        setup[20] = 1;
+        setup[21] = 1;
+        setup.extend_from_slice(&[24, 32, 32, 0, 0, 0, 0, 0]);
+        let mut screen = vec![0; 40];
+        screen[..4].copy_from_slice(&0x1234u32.to_le_bytes());
+        screen[20..22].copy_from_slice(&640u16.to_le_bytes());
+        screen[22..24].copy_from_slice(&480u16.to_le_bytes());
+        screen[38] = 24;
+        setup.extend_from_slice(&screen);
+        let mut response = vec![1, 0, 11, 0, 0, 0];
+        response.extend_from_slice(&((setup.len() / 4) as u16).to_le_bytes());
+        response.extend_from_slice(&setup);
+        server.write_all(&response).unwrap();
+        script(&mut server);
+    });
+    (Connection::setup(client, None, 0).unwrap(), server)
+}
+
+/// Read the next request the client sent to a fake server
+#[cfg(test)]
+fn read_request(server: &mut UnixStream) -> Vec<u8> {
+    let mut request = vec![0; 4];
+    server.read_exact(&mut request).unwrap();
+    request.resize(u16::from_le_bytes([request[2], request[3]]) as usize * 4, 0);
+    server.read_exact(&mut request[4..]).unwrap();
+    request
+}
+
+/// A reply to the request `sequence`, with `body` following its length field.
+/// The first 24 bytes of `body` are the fixed part of the reply, the rest its additional data.
+#[cfg(test)]
+fn reply(sequence: u16, body: &[u8]) -> Vec<u8> {
+    let extra = pad(body.len().saturating_sub(24));
+    let mut reply = vec![1, 0];
+    reply.extend_from_slice(&sequence.to_le_bytes());
+    reply.extend_from_slice(&((extra / 4) as u32).to_le_bytes());
+    reply.extend_from_slice(body);
+    reply.resize(32 + extra, 0);
+    reply
+}
+
+#[test]
+fn speaks_the_protocol_over_a_socket() {
+    let (mut connection, server) = fake_server(|server| {
+        // QueryPointer, answered after an unrelated event
+        assert_eq!(read_request(server), [38, 0, 2, 0, 0x34, 0x12, 0, 0]);
+        server.write_all(&[12; 32]).unwrap();
+        let mut body = [0; 12];
+        body[8..10].copy_from_slice(&12i16.to_le_bytes());
+        body[10..12].copy_from_slice(&(-34i16).to_le_bytes());
+        server.write_all(&reply(1, &body)).unwrap();
+    });
+
+    assert_eq!(connection.screen(), &Screen { root: 0x1234, width: 640, height: 480, root_depth: 24 });
+    assert_eq!(connection.root_mouse_position().unwrap(), (12, -34));
+    assert_eq!(connection.generate_id(), 0x0040_0001);
+    server.join().unwrap();
+}
+
+#[test]
+fn captures_through_get_image() {
+    let pixels: Vec<u8> = (0..16).collect();
+    let expected = pixels.clone();
+    let (mut connection, server) = fake_server(move |server| {
+        // GetImage of 2x2 pixels at (3, 4) in ZPixmap format, of all planes
+        assert_eq!(read_request(server), [73, 2, 5, 0, 0x34, 0x12, 0, 0, 3, 0, 4, 0, 2, 0, 2, 0, 0xff, 0xff, 0xff, 0xff]);
+        let mut body = vec![0; 24];
+        body.extend_from_slice(&pixels);
+        server.write_all(&reply(1, &body)).unwrap();
+
+        // A reply lacking the pixels of the last row
+        read_request(server);
+        let mut body = vec![0; 24];
+        body.extend_from_slice(&pixels[..8]);
+        server.write_all(&reply(2, &body)).unwrap();
+    });
+
+    let capture = connection.capture_area((3, 4), (2, 2)).unwrap();
+    assert_eq!((capture.width(), capture.height()), (2, 2));
+    assert_eq!(capture.as_bytes(), &expected[..]);
+    assert!(matches!(connection.capture_area((3, 4), (2, 2)), Err(WireError::Protocol)));
+    server.join().unwrap();
+}
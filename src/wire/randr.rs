@@ -0,0 +1,131 @@
+//! Querying the RandR monitors over the wire
+
+use super::{Connection, Reader, WireError};
+
+/// A monitor as reported by RandR, like the `Monitor` of the `xrandr` feature
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    name: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    primary: bool
+}
+
+impl Monitor {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+    pub fn primary(&self) -> bool {
+        self.primary
+    }
+}
+
+impl Connection {
+    /// The monitors of the screen.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the server lacks RandR 1.5.
+    pub fn monitors(&mut self) -> Result<Vec<Monitor>, WireError> {
+        let opcode = self.extension("RANDR")?.ok_or(WireError::ExtensionNotAvailable)?;
+        // The server answers with the requests of the version the client announced
+        if !self.randr_version {
+            let mut body = 1u32.to_le_bytes().to_vec();
+            body.extend_from_slice(&5u32.to_le_bytes());
+            // RRQueryVersion
+            let sequence = self.request(opcode, 0, &body)?;
+            let reply = self.reply(sequence)?;
+            let mut reader = Reader::new(&reply, 8);
+            if (reader.u32()?, reader.u32()?) < (1, 5) {
+                return Err(WireError::ExtensionNotAvailable);
+            }
+            self.randr_version = true;
+        }
+
+        let mut body = self.screen.root.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        // RRGetMonitors, including inactive ones like XRRGetMonitors(.., False, ..)
+        let sequence = self.request(opcode, 42, &body)?;
+        let reply = self.reply(sequence)?;
+        let count = Reader::new(&reply, 12).u32()?;
+        let mut reader = Reader::new(&reply, 32);
+        let mut monitors = Vec::new();
+        for _ in 0..count {
+            let name = reader.u32()?;
+            let primary = reader.u8()? != 0;
+            reader.skip(1)?;
+            let outputs = reader.u16()? as usize;
+            let (x, y) = (reader.i16()? as i32, reader.i16()? as i32);
+            let (width, height) = (reader.u16()? as i32, reader.u16()? as i32);
+            reader.skip(8 + outputs * 4)?;
+            monitors.push((name, Monitor { name: String::new(), x, y, width, height, primary }));
+        }
+        monitors.into_iter().map(|(name, mut monitor)| {
+            monitor.name = self.atom_name(name)?;
+            Ok(monitor)
+        }).collect()
+    }
+}
+
+#[test]
+fn queries_monitors() {
+    use std::io::Write;
+    use super::{fake_server, read_request, reply};
+    let (mut connection, server) = fake_server(|server| {
+        // QueryExtension, answering with the major opcode 140
+        let request = read_request(server);
+        assert_eq!((request[0], &request[8..13]), (98, &b"RANDR"[..]));
+        server.write_all(&reply(1, &[1, 140])).unwrap();
+
+        // RRQueryVersion 1.5
+        assert_eq!(read_request(server), [140, 0, 3, 0, 1, 0, 0, 0, 5, 0, 0, 0]);
+        server.write_all(&reply(2, &[1, 0, 0, 0, 5, 0, 0, 0])).unwrap();
+
+        // RRGetMonitors of the root window, with inactive monitors
+        assert_eq!(read_request(server), [140, 42, 3, 0, 0x34, 0x12, 0, 0, 0, 0, 0, 0]);
+        let mut body = vec![0; 24];
+        body[4..8].copy_from_slice(&2u32.to_le_bytes());
+        for (atom, primary, x, width, outputs) in [(0x50u32, 1u8, 0i16, 1920u16, 1u16), (0x51, 0, 1920, 1280, 0)] {
+            body.extend_from_slice(&atom.to_le_bytes());
+            body.extend_from_slice(&[primary, 0]);
+            body.extend_from_slice(&outputs.to_le_bytes());
+            body.extend_from_slice(&x.to_le_bytes());
+            body.extend_from_slice(&0i16.to_le_bytes());
+            body.extend_from_slice(&width.to_le_bytes());
+            body.extend_from_slice(&1080u16.to_le_bytes());
+            body.extend_from_slice(&[0; 8]);
+            body.extend(std::iter::repeat_n(0x7f, outputs as usize * 4));
+        }
+        server.write_all(&reply(3, &body)).unwrap();
+
+        // GetAtomName of each monitor
+        for (sequence, atom, name) in [(4, 0x50u8, &b"DP-1"[..]), (5, 0x51, &b"HDMI-1"[..])] {
+            assert_eq!(read_request(server), [17, 0, 2, 0, atom, 0, 0, 0]);
+            let mut body = vec![name.len() as u8, 0];
+            body.resize(24, 0);
+            body.extend_from_slice(name);
+            server.write_all(&reply(sequence, &body)).unwrap();
+        }
+    });
+
+    let monitors = connection.monitors().unwrap();
+    assert_eq!(monitors, [
+        Monitor { name: "DP-1".into(), x: 0, y: 0, width: 1920, height: 1080, primary: true },
+        Monitor { name: "HDMI-1".into(), x: 1920, y: 0, width: 1280, height: 1080, primary: false }
+    ]);
+    server.join().unwrap();
+}
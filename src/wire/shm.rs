@@ -0,0 +1,138 @@
+//! Capturing through MIT-SHM over the wire, into a System V shared memory segment
+
+use crate::codec::Pixels;
+use super::{Connection, WireError};
+
+/// Captures an area of the screen into shared memory, see [`Connection::shm`].
+///
+/// The segment is attached to the server until the capture is dropped.
+pub struct ShmCapture<'c> {
+    connection: &'c mut Connection,
+    opcode: u8,
+    segment: u32,
+    address: *mut u8,
+    offset: (u32, u32),
+    area: (u32, u32)
+}
+
+impl Connection {
+    /// Capture the area of `area` (width, height) at `offset` (x, y) through MIT-SHM.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the server lacks MIT-SHM, or can't attach the segment, e.g. as it runs in another IPC namespace.
+    pub fn shm(&mut self, offset: (u32, u32), area: (u32, u32)) -> Result<ShmCapture<'_>, WireError> {
+        if !self.bgrx {
+            return Err(WireError::UnsupportedFormat);
+        }
+        let opcode = self.extension("MIT-SHM")?.ok_or(WireError::ExtensionNotAvailable)?;
+        let size = (area.0 as usize * area.1 as usize * 4).max(1);
+        let id = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+        if id == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let address = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+        if address as isize == -1 {
+            let error = std::io::Error::last_os_error();
+            unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+            return Err(error.into());
+        }
+
+        let segment = self.generate_id();
+        let mut body = segment.to_le_bytes().to_vec();
+        body.extend_from_slice(&(id as u32).to_le_bytes());
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        // ShmAttach, whose errors only show up once the server processed it
+        let attached = self.request(opcode, 1, &body).and_then(|_| self.roundtrip());
+        // Once attached by both sides, the segment is freed as soon as both detach
+        unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+        if let Err(error) = attached {
+            unsafe { libc::shmdt(address) };
+            return Err(error);
+        }
+        Ok(ShmCapture { connection: self, opcode, segment, address: address as *mut u8, offset, area })
+    }
+}
+
+impl<'c> ShmCapture<'c> {
+    /// Capture the area into the segment, valid until the next capture
+    pub fn capture(&mut self) -> Result<Pixels<'_>, WireError> {
+        let mut body = self.connection.screen.root.to_le_bytes().to_vec();
+        for value in [self.offset.0, self.offset.1, self.area.0, self.area.1] {
+            body.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        body.extend_from_slice(&u32::MAX.to_le_bytes());
+        // ZPixmap
+        body.extend_from_slice(&[2, 0, 0, 0]);
+        body.extend_from_slice(&self.segment.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        // ShmGetImage
+        let sequence = self.connection.request(self.opcode, 4, &body)?;
+        self.connection.reply(sequence)?;
+
+        let (width, height) = (self.area.0 as usize, self.area.1 as usize);
+        let data = unsafe { std::slice::from_raw_parts(self.address, width * height * 4) };
+        Ok(Pixels::new(data, width, height, width * 4).expect("segment holds the whole area"))
+    }
+    /// Move the captured area to `offset` (x, y), keeping its size
+    pub fn set_offset(&mut self, offset: (u32, u32)) {
+        self.offset = offset;
+    }
+    pub fn offset(&self) -> (u32, u32) {
+        self.offset
+    }
+    pub fn area(&self) -> (u32, u32) {
+        self.area
+    }
+}
+
+impl<'c> Drop for ShmCapture<'c> {
+    fn drop(&mut self) {
+        // ShmDetach
+        let _ = self.connection.request(self.opcode, 2, &self.segment.to_le_bytes())
+            .and_then(|_| self.connection.roundtrip());
+        unsafe { libc::shmdt(self.address as *const libc::c_void) };
+    }
+}
+
+#[test]
+fn captures_into_shared_memory() {
+    use std::io::Write;
+    use super::{fake_server, read_request, reply};
+    let (mut connection, server) = fake_server(|server| {
+        let request = read_request(server);
+        assert_eq!((request[0], &request[8..15]), (98, &b"MIT-SHM"[..]));
+        server.write_all(&reply(1, &[1, 130])).unwrap();
+
+        // ShmAttach of the first id of the client, read-write, followed by GetInputFocus
+        let request = read_request(server);
+        assert_eq!((&request[..8], &request[12..]), (&[130, 1, 4, 0, 1, 0, 0x40, 0][..], &[0, 0, 0, 0][..]));
+        let id = i32::from_le_bytes([request[8], request[9], request[10], request[11]]);
+        let address = unsafe { libc::shmat(id, std::ptr::null(), 0) } as *mut u8;
+        assert_ne!(address as isize, -1);
+        assert_eq!(read_request(server), [43, 0, 1, 0]);
+        server.write_all(&reply(3, &[])).unwrap();
+
+        // ShmGetImage of 2x1 pixels at (5, 6) in ZPixmap format, at the start of the segment
+        assert_eq!(read_request(server), [
+            130, 4, 8, 0, 0x34, 0x12, 0, 0, 5, 0, 6, 0, 2, 0, 1, 0, 0xff, 0xff, 0xff, 0xff,
+            2, 0, 0, 0, 1, 0, 0x40, 0, 0, 0, 0, 0
+        ]);
+        unsafe {
+            std::ptr::copy_nonoverlapping([1u8, 2, 3, 0, 4, 5, 6, 0].as_ptr(), address, 8);
+            libc::shmdt(address as *const libc::c_void);
+        }
+        server.write_all(&reply(4, &[])).unwrap();
+
+        // ShmDetach when the capture is dropped
+        assert_eq!(read_request(server), [130, 2, 2, 0, 1, 0, 0x40, 0]);
+        assert_eq!(read_request(server), [43, 0, 1, 0]);
+        server.write_all(&reply(6, &[])).unwrap();
+    });
+
+    let mut shm = connection.shm((5, 6), (2, 1)).unwrap();
+    let pixels = shm.capture().unwrap();
+    assert_eq!(pixels.row(0), [1, 2, 3, 0, 4, 5, 6, 0]);
+    drop(shm);
+    server.join().unwrap();
+}
@@ -0,0 +1,275 @@
+//!
+//! Conversion of captures into YUV, as consumed by video encoders.
+//!
+//! The BGRX pixels of an [`Image`] are converted into
+//! - I420: planar, with one U and V sample per 2x2 pixels (`yuv420p`)
+//! - NV12: a Y plane, followed by a plane of interleaved U and V samples per 2x2 pixels
+//! - YUY2: packed `Y0 U Y1 V`, with one U and V sample per 2 pixels horizontally (`yuyv422`)
+//!
+//! Chroma samples are the average of the pixels they cover. Images of odd sizes are supported,
+//! the chroma planes are rounded up to cover the last row and column.
+//! The planes are supplied by the caller through [`Plane`], with their own stride, so frames can be
+//! converted straight into the buffers of an encoder.
+//!
+//! # Usage
+//! ```rust
+//! # use rxscreen::{Display, yuv::{Conversion, Matrix, Range, YuvFormat}};
+//! if let Ok(display) = Display::new(":0.0") {
+//!     let capture = display.capture().unwrap();
+//!     // A tightly packed I420 frame, e.g. for `x264 --input-res 1920x1080 -`
+//!     let frame = capture.to_yuv(YuvFormat::I420, Conversion::new(Matrix::Bt709, Range::Limited));
+//! }
+//! ```
+//!
+
+use crate::{Image, codec::Pixels};
+
+#[derive(PartialEq, Debug)]
+pub enum YuvError {
+    /// A plane is too small for the image, or its stride too short for a row
+    PlaneTooSmall
+}
+
+/// The matrix used to derive luma and chroma from RGB
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Matrix {
+    /// ITU-R BT.601, used for SD video
+    Bt601,
+    /// ITU-R BT.709, used for HD video
+    Bt709
+}
+
+/// The range of the YUV values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Range {
+    /// 16 to 235 for luma and 16 to 240 for chroma, as expected by most encoders and players
+    Limited,
+    /// 0 to 255
+    Full
+}
+
+/// The matrix and range of a conversion, BT.601 in limited range by default
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    pub matrix: Matrix,
+    pub range: Range
+}
+
+impl Conversion {
+    pub fn new(matrix: Matrix, range: Range) -> Self {
+        Self { matrix, range }
+    }
+}
+
+impl Default for Conversion {
+    fn default() -> Self {
+        Self::new(Matrix::Bt601, Range::Limited)
+    }
+}
+
+/// The YUV layouts frames can be converted into, see the [module documentation](self)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YuvFormat {
+    I420,
+    Nv12,
+    Yuy2
+}
+
+impl YuvFormat {
+    /// The size of a tightly packed frame of `width` x `height` pixels
+    pub fn frame_size(&self, width: usize, height: usize) -> usize {
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        match self {
+            YuvFormat::I420 | YuvFormat::Nv12 => width * height + chroma * 2,
+            YuvFormat::Yuy2 => width.div_ceil(2) * 4 * height
+        }
+    }
+}
+
+/// A plane supplied by the caller, with rows of `stride` bytes
+#[derive(Debug)]
+pub struct Plane<'a> {
+    pub data: &'a mut [u8],
+    pub stride: usize
+}
+
+impl<'a> Plane<'a> {
+    pub fn new(data: &'a mut [u8], stride: usize) -> Self {
+        Self { data, stride }
+    }
+    /// Make sure the plane holds `rows` rows of `length` bytes
+    fn check(&self, length: usize, rows: usize) -> Result<(), YuvError> {
+        if rows > 0 && (self.stride < length || self.data.len() < self.stride * (rows - 1) + length) {
+            return Err(YuvError::PlaneTooSmall);
+        }
+        Ok(())
+    }
+    fn row(&mut self, y: usize, length: usize) -> &mut [u8] {
+        &mut self.data[y * self.stride..y * self.stride + length]
+    }
+}
+
+/// Fixed point coefficients of a [`Conversion`], scaled by 2^16
+struct Coefficients {
+    y: [i32; 3],
+    u: [i32; 3],
+    v: [i32; 3],
+    y_offset: i32
+}
+
+impl Coefficients {
+    fn new(conversion: Conversion) -> Self {
+        let (kr, kb) = match conversion.matrix {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722)
+        };
+        let kg = 1.0 - kr - kb;
+        let (luma, chroma, y_offset) = match conversion.range {
+            Range::Limited => (219.0 / 255.0, 224.0 / 255.0, 16),
+            Range::Full => (1.0, 1.0, 0)
+        };
+        let fixed = |values: [f64; 3], scale: f64| values.map(|value| (value * scale * 65536.0).round() as i32);
+        Self {
+            y: fixed([kr, kg, kb], luma),
+            u: fixed([-kr / (2.0 * (1.0 - kb)), -kg / (2.0 * (1.0 - kb)), 0.5], chroma),
+            v: fixed([0.5, -kg / (2.0 * (1.0 - kr)), -kb / (2.0 * (1.0 - kr))], chroma),
+            y_offset
+        }
+    }
+    fn apply(weights: &[i32; 3], [r, g, b]: [i32; 3]) -> i32 {
+        (weights[0] * r + weights[1] * g + weights[2] * b + 32768) >> 16
+    }
+    fn y(&self, rgb: [i32; 3]) -> u8 {
+        (Self::apply(&self.y, rgb) + self.y_offset).clamp(0, 255) as u8
+    }
+    fn uv(&self, rgb: [i32; 3]) -> (u8, u8) {
+        ((Self::apply(&self.u, rgb) + 128).clamp(0, 255) as u8, (Self::apply(&self.v, rgb) + 128).clamp(0, 255) as u8)
+    }
+}
+
+fn rgb(bgrx: &[u8]) -> [i32; 3] {
+    [bgrx[2] as i32, bgrx[1] as i32, bgrx[0] as i32]
+}
+
+fn write_luma(pixels: &Pixels, coefficients: &Coefficients, plane: &mut Plane) {
+    for y in 0..pixels.height() {
+        for (target, bgrx) in plane.row(y, pixels.width()).iter_mut().zip(pixels.row(y).chunks_exact(4)) {
+            *target = coefficients.y(rgb(bgrx));
+        }
+    }
+}
+
+/// Pass the U and V sample of each 2x2 block, averaged over its pixels, to `sample` with the block's position
+fn subsample(pixels: &Pixels, coefficients: &Coefficients, mut sample: impl FnMut(usize, usize, (u8, u8))) {
+    let (width, height) = (pixels.width(), pixels.height());
+    for cy in 0..height.div_ceil(2) {
+        let rows = [pixels.row(cy * 2), pixels.row((cy * 2 + 1).min(height - 1))];
+        for cx in 0..width.div_ceil(2) {
+            let mut sum = [0; 3];
+            for row in rows {
+                for x in [cx * 2, (cx * 2 + 1).min(width - 1)] {
+                    sum.iter_mut().zip(rgb(&row[x * 4..])).for_each(|(sum, value)| *sum += value);
+                }
+            }
+            sample(cx, cy, coefficients.uv(sum.map(|sum| (sum + 2) / 4)));
+        }
+    }
+}
+
+/// Convert `pixels` into the planes of an I420 frame
+pub fn convert_i420(pixels: &Pixels, mut y: Plane, u: Plane, v: Plane, conversion: Conversion) -> Result<(), YuvError> {
+    let (chroma_width, chroma_height) = (pixels.width().div_ceil(2), pixels.height().div_ceil(2));
+    y.check(pixels.width(), pixels.height())?;
+    u.check(chroma_width, chroma_height)?;
+    v.check(chroma_width, chroma_height)?;
+
+    let coefficients = Coefficients::new(conversion);
+    write_luma(pixels, &coefficients, &mut y);
+    subsample(pixels, &coefficients, |x, row, (cb, cr)| {
+        u.data[row * u.stride + x] = cb;
+        v.data[row * v.stride + x] = cr;
+    });
+    Ok(())
+}
+
+/// Convert `pixels` into the planes of an NV12 frame
+pub fn convert_nv12(pixels: &Pixels, mut y: Plane, uv: Plane, conversion: Conversion) -> Result<(), YuvError> {
+    y.check(pixels.width(), pixels.height())?;
+    uv.check(pixels.width().div_ceil(2) * 2, pixels.height().div_ceil(2))?;
+
+    let coefficients = Coefficients::new(conversion);
+    write_luma(pixels, &coefficients, &mut y);
+    subsample(pixels, &coefficients, |x, row, (cb, cr)| {
+        uv.data[row * uv.stride + x * 2] = cb;
+        uv.data[row * uv.stride + x * 2 + 1] = cr;
+    });
+    Ok(())
+}
+
+/// Convert `pixels` into a YUY2 frame
+pub fn convert_yuy2(pixels: &Pixels, mut yuy2: Plane, conversion: Conversion) -> Result<(), YuvError> {
+    let width = pixels.width();
+    yuy2.check(width.div_ceil(2) * 4, pixels.height())?;
+
+    let coefficients = Coefficients::new(conversion);
+    for y in 0..pixels.height() {
+        let source = pixels.row(y);
+        for (x, target) in yuy2.row(y, width.div_ceil(2) * 4).chunks_exact_mut(4).enumerate() {
+            let (left, right) = (rgb(&source[x * 8..]), rgb(&source[(x * 2 + 1).min(width - 1) * 4..]));
+            let (cb, cr) = coefficients.uv([0, 1, 2].map(|channel| (left[channel] + right[channel] + 1) / 2));
+            target.copy_from_slice(&[coefficients.y(left), cb, coefficients.y(right), cr]);
+        }
+    }
+    Ok(())
+}
+
+/// Convert `pixels` into a tightly packed frame in `format`, stored in `frame`.
+/// `frame` has to hold at least [`YuvFormat::frame_size`] bytes.
+pub fn convert(pixels: &Pixels, format: YuvFormat, conversion: Conversion, frame: &mut [u8]) -> Result<(), YuvError> {
+    let (width, height) = (pixels.width(), pixels.height());
+    if frame.len() < format.frame_size(width, height) {
+        return Err(YuvError::PlaneTooSmall);
+    }
+    let chroma_width = width.div_ceil(2);
+    match format {
+        YuvFormat::I420 => {
+            let (y, chroma) = frame.split_at_mut(width * height);
+            let (u, v) = chroma.split_at_mut(chroma_width * height.div_ceil(2));
+            convert_i420(pixels, Plane::new(y, width), Plane::new(u, chroma_width), Plane::new(v, chroma_width), conversion)
+        },
+        YuvFormat::Nv12 => {
+            let (y, uv) = frame.split_at_mut(width * height);
+            convert_nv12(pixels, Plane::new(y, width), Plane::new(uv, chroma_width * 2), conversion)
+        },
+        YuvFormat::Yuy2 => convert_yuy2(pixels, Plane::new(frame, chroma_width * 4), conversion)
+    }
+}
+
+impl Image {
+    /// Convert the image into a tightly packed frame in `format`
+    pub fn to_yuv(&self, format: YuvFormat, conversion: Conversion) -> Vec<u8> {
+        let pixels = self.pixels();
+        let mut frame = vec![0; format.frame_size(pixels.width(), pixels.height())];
+        convert(&pixels, format, conversion, &mut frame).expect("frame has the size of the image");
+        frame
+    }
+}
+
+#[test]
+fn converts_into_every_layout() {
+    // 3x1 image: white, black, red
+    let data = [255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 255, 0];
+    let pixels = Pixels::new(&data, 3, 1, 12).unwrap();
+    let mut frame = [0; 7];
+    convert(&pixels, YuvFormat::I420, Conversion::default(), &mut frame).unwrap();
+    // Luma of every pixel, then one chroma sample each for U and V per 2x2 block
+    assert_eq!(frame, [235, 16, 81, 128, 90, 128, 240]);
+
+    convert(&pixels, YuvFormat::Nv12, Conversion::new(Matrix::Bt709, Range::Full), &mut frame).unwrap();
+    assert_eq!(frame, [255, 0, 54, 128, 128, 99, 255]);
+
+    let mut frame = [0; 8];
+    convert(&pixels, YuvFormat::Yuy2, Conversion::default(), &mut frame).unwrap();
+    assert_eq!(frame, [235, 128, 16, 128, 81, 90, 81, 240]);
+    assert_eq!(convert(&pixels, YuvFormat::Yuy2, Conversion::default(), &mut [0; 7]), Err(YuvError::PlaneTooSmall));
+}